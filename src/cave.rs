@@ -1,6 +1,8 @@
 use bevy::prelude::*;
 use bevy_prototype_lyon::prelude::*;
-use crate::player::{Player, PLAYER_WIDTH, PLAYER_HEIGHT, SquarePos};
+use crate::player::{Player, PLAYER_WIDTH, PLAYER_HEIGHT, SquarePos, get_dimens};
+use crate::outline::{outline_path, OutlineStyle};
+use crate::map::Level;
 
 const INNER_COLOR: Color = Color::Rgba {
     red: 0.9765625,
@@ -9,6 +11,41 @@ const INNER_COLOR: Color = Color::Rgba {
     alpha: 1.0,
 };
 
+const OUTLINE_COLOR: Color = Color::Rgba {
+    red: 0.8359375,
+    green: 0.7734375,
+    blue: 0.265625,
+    alpha: 1.0,
+};
+
+const OUTLINE_WIDTH: f32 = 2.0;
+
+const HINT_OUTLINE_COLOR: Color = Color::Rgba {
+    red: 0.14453125,
+    green: 0.125,
+    blue: 0.19140625,
+    alpha: 0.8,
+};
+
+const HINT_EXTRA_COLOR: Color = Color::Rgba {
+    red: 0.890625,
+    green: 0.23046875,
+    blue: 0.2109375,
+    alpha: 0.6,
+};
+
+const HINT_MISSING_COLOR: Color = Color::Rgba {
+    red: 0.3515625,
+    green: 0.78515625,
+    blue: 0.3359375,
+    alpha: 0.6,
+};
+
+const HINT_OUTLINE_WIDTH: f32 = 2.0;
+
+#[derive(Resource)]
+pub struct CaveHintsEnabled(pub bool);
+
 #[derive(Component, Clone)]
 pub struct Cave {
     pub position: Vec2,
@@ -17,45 +54,49 @@ pub struct Cave {
 
 impl Cave {
     pub fn get_dimens(&self) -> (i32, i32) {
-        let mut max_x = 0;
-        let mut max_y = 0;
-        for square in &self.squares {
-            if square.0 + 1 > max_x {
-                max_x = square.0 + 1;
-            }
-            if square.1 + 1 > max_y {
-                max_y = square.1 + 1;
-            }
-        }
-        (max_x, max_y)
+        get_dimens(&self.squares)
     }
 
     pub fn get_shape(&self) -> Path {
-        let mut builder = GeometryBuilder::new();
-        for square in &self.squares {
-            builder = builder.add(&shapes::Rectangle {
-                extents: Vec2::new(PLAYER_WIDTH, PLAYER_HEIGHT),
-                origin: RectangleOrigin::CustomCenter(Vec2::new(PLAYER_WIDTH * square.0 as f32, PLAYER_HEIGHT * square.1 as f32)),
-            });
-        }
-        builder.build()
+        outline_path(&self.squares, OutlineStyle::CAVE)
     }
 
-    pub fn matches_player(&self, player: &Player) -> bool {
-        if self.squares.len() != player.squares.len() {
-            return false;
+    /// Where square (0, 0) of the cave sits.
+    pub fn origin(&self) -> Vec2 {
+        let (w, h) = self.get_dimens();
+        Vec2::new(
+            self.position.x - w as f32 * PLAYER_WIDTH / 2.0,
+            self.position.y - h as f32 * PLAYER_HEIGHT / 2.0,
+        )
+    }
+
+    /// Checks whether the given players fill the cave exactly between them, either one alone or several
+    /// side by side. Returns the players inside along with the grid offset each one sits at.
+    pub fn filled_by<'a>(
+        &self,
+        players: impl IntoIterator<Item = (Entity, &'a Player, Vec2)>,
+    ) -> Option<Vec<(Entity, IVec2)>> {
+        if self.squares.is_empty() {
+            return None;
         }
-        for square in &self.squares {
-            let mut found: bool = false;
-            for square2 in &player.squares {
-                if square.0 == square2.0 && square.1 == square2.1 {
-                    found = true;
-                    break;
-                }
+        let mut covered: Vec<SquarePos> = vec![];
+        let mut filling = vec![];
+        for (entity, player, position) in players {
+            let offset = ((position - self.origin()) / Vec2::new(PLAYER_WIDTH, PLAYER_HEIGHT)).round().as_ivec2();
+            let squares: Vec<SquarePos> = player.squares.iter()
+                .map(|square| SquarePos(square.0 + offset.x, square.1 + offset.y))
+                .collect();
+            if !squares.iter().any(|square| self.squares.contains(square)) {
+                continue;
             }
-            if !found { return false; }
+            // sticking out of the cave or overlapping another player
+            if squares.iter().any(|square| !self.squares.contains(square) || covered.contains(square)) {
+                return None;
+            }
+            covered.extend(squares);
+            filling.push((entity, offset));
         }
-        true
+        (covered.len() == self.squares.len()).then_some(filling)
     }
 }
 
@@ -63,25 +104,141 @@ impl Cave {
 pub struct CaveBundle {
     shape_bundle: ShapeBundle,
     fill: Fill,
+    stroke: Stroke,
     cave: Cave,
 }
 
 impl CaveBundle {
     pub fn new(cave: Cave) -> Self {
         let shape = cave.get_shape();
-        let (w, h) = cave.get_dimens();
+        let origin = cave.origin();
         CaveBundle {
             shape_bundle: ShapeBundle {
-                path: GeometryBuilder::build_as(&shape),
-                transform: Transform::from_xyz(
-                    cave.position.x - w as f32 * PLAYER_WIDTH / 2.0,
-                    cave.position.y - h as f32 * PLAYER_HEIGHT / 2.0,
-                    -1.0
-                ),
+                path: shape,
+                transform: Transform::from_xyz(origin.x, origin.y, -1.0),
                 ..default()
             },
             fill: Fill::color(INNER_COLOR),
+            stroke: Stroke::new(OUTLINE_COLOR, OUTLINE_WIDTH),
             cave,
         }
     }
 }
+
+
+#[derive(Component)]
+pub struct CaveHint {
+    cave: Entity,
+    squares: Vec<SquarePos>,
+}
+
+#[derive(Component)]
+pub enum CaveHintLayer {
+    Outline,
+    Extra,
+    Missing,
+}
+
+impl CaveHintLayer {
+    /// The squares this layer covers, in the cave's grid.
+    fn squares(&self, cave: &Cave, player_squares: &[SquarePos]) -> Vec<SquarePos> {
+        match self {
+            CaveHintLayer::Outline => player_squares.to_vec(),
+            CaveHintLayer::Extra => player_squares.iter()
+                .filter(|square| !cave.squares.contains(square))
+                .copied()
+                .collect(),
+            CaveHintLayer::Missing => cave.squares.iter()
+                .filter(|square| !player_squares.contains(square))
+                .copied()
+                .collect(),
+        }
+    }
+}
+
+fn spawn_cave_hint(
+    commands: &mut Commands,
+    cave_entity: Entity,
+    cave: &Cave,
+    cave_transform: &Transform,
+    player_squares: &[SquarePos],
+) {
+    commands.spawn((
+        SpatialBundle::from_transform(Transform::from_xyz(
+            cave_transform.translation.x,
+            cave_transform.translation.y,
+            cave_transform.translation.z + 0.5,
+        )),
+        CaveHint { cave: cave_entity, squares: player_squares.to_vec() },
+    )).with_children(|parent| {
+        for (layer, z) in [(CaveHintLayer::Missing, 0.0), (CaveHintLayer::Extra, 0.1), (CaveHintLayer::Outline, 0.2)] {
+            let shape_bundle = ShapeBundle {
+                path: outline_path(&layer.squares(cave, player_squares), OutlineStyle::CAVE),
+                transform: Transform::from_xyz(0.0, 0.0, z),
+                ..default()
+            };
+            match layer {
+                CaveHintLayer::Outline => parent.spawn((
+                    shape_bundle,
+                    Stroke::new(HINT_OUTLINE_COLOR, HINT_OUTLINE_WIDTH),
+                    layer,
+                )),
+                CaveHintLayer::Extra => parent.spawn((shape_bundle, Fill::color(HINT_EXTRA_COLOR), layer)),
+                CaveHintLayer::Missing => parent.spawn((shape_bundle, Fill::color(HINT_MISSING_COLOR), layer)),
+            };
+        }
+    });
+}
+
+pub fn toggle_cave_hints(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut cave_hints_enabled: ResMut<CaveHintsEnabled>,
+) {
+    if keyboard_input.just_pressed(KeyCode::H) {
+        cave_hints_enabled.0 = !cave_hints_enabled.0;
+    }
+}
+
+pub fn update_cave_hints(
+    mut commands: Commands,
+    cave_hints_enabled: Res<CaveHintsEnabled>,
+    players: Query<&Player>,
+    caves: Query<(Entity, &Cave, &Transform), With<Level>>,
+    mut hints: Query<(Entity, &mut CaveHint, &Children)>,
+    mut layers: Query<(&CaveHintLayer, &mut Path)>,
+) {
+    // in co-op the hints follow the first player
+    let player_squares = players.iter().find(|player| player.index == 0).map(|player| &player.squares);
+
+    for (hint_entity, mut hint, children) in &mut hints {
+        let cave = caves.get(hint.cave).ok();
+        match (cave, player_squares) {
+            (Some((_, cave, _)), Some(player_squares)) if cave_hints_enabled.0 => {
+                if hint.squares == *player_squares {
+                    continue;
+                }
+                for &child in children {
+                    if let Ok((layer, mut path)) = layers.get_mut(child) {
+                        *path = outline_path(&layer.squares(cave, player_squares), OutlineStyle::CAVE);
+                    }
+                }
+                hint.squares = player_squares.clone();
+            },
+            _ => {
+                commands.entity(hint_entity).despawn_recursive();
+            },
+        }
+    }
+
+    if !cave_hints_enabled.0 {
+        return;
+    }
+    if let Some(player_squares) = player_squares {
+        for (cave_entity, cave, cave_transform) in &caves {
+            if cave.squares.is_empty() || hints.iter().any(|(_, hint, _)| hint.cave == cave_entity) {
+                continue;
+            }
+            spawn_cave_hint(&mut commands, cave_entity, cave, cave_transform, player_squares);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use crate::player::{Player, SquarePos, PLAYER_WIDTH, PLAYER_HEIGHT, PLAYER_COLOR};
+use crate::transformer::TransformerAnimState;
+use crate::map::{Level, LevelSet, BUTTON_COLOR};
+use crate::input::PLAYER_COUNT;
+
+const CHECKPOINT_RADIUS: f32 = 30.0;
+const POLE_WIDTH: f32 = 4.0;
+const POLE_HEIGHT: f32 = 60.0;
+const FLAG_WIDTH: f32 = 24.0;
+const FLAG_HEIGHT: f32 = 16.0;
+
+const INACTIVE_COLOR: Color = BUTTON_COLOR;
+const ACTIVE_COLOR: Color = PLAYER_COLOR;
+
+#[derive(Component)]
+pub struct Checkpoint {
+    position: Vec2,
+    radius: f32,
+}
+
+pub struct CheckpointSave {
+    set: LevelSet,
+    levelid: usize,
+    checkpoint: Entity,
+    position: (f32, f32),
+    squares: Vec<SquarePos>,
+    carried: Vec<Entity>,
+}
+
+/// Where to respawn, in what shape, and the loose squares stuck onto it.
+type RespawnState = ((f32, f32), Vec<SquarePos>, Vec<Entity>);
+
+/// The last checkpoint each player touched, with the shape they had when they touched it.
+#[derive(Resource, Default)]
+pub struct ActiveCheckpoint(pub [Option<CheckpointSave>; PLAYER_COUNT]);
+
+impl ActiveCheckpoint {
+    /// Where and in what shape to respawn in this level, if the player has reached a checkpoint in it.
+    pub fn respawn_for(&self, level: &Level, index: usize) -> Option<RespawnState> {
+        match &self.0[index] {
+            Some(save) if save.set == level.set && save.levelid == level.levelid => {
+                Some((save.position, save.squares.clone(), save.carried.clone()))
+            },
+            _ => None,
+        }
+    }
+}
+
+fn flag_shape() -> Path {
+    // the checkpoint's position is where the player respawns, so the pole stands on the ground below it
+    let ground = -PLAYER_HEIGHT / 2.0;
+    let pole_x = -PLAYER_WIDTH / 2.0;
+    let top = ground + POLE_HEIGHT;
+    GeometryBuilder::new()
+        .add(&shapes::Rectangle {
+            extents: Vec2::new(POLE_WIDTH, POLE_HEIGHT),
+            origin: RectangleOrigin::CustomCenter(Vec2::new(pole_x, ground + POLE_HEIGHT / 2.0)),
+        })
+        .add(&shapes::Polygon {
+            points: vec![
+                Vec2::new(pole_x, top),
+                Vec2::new(pole_x + FLAG_WIDTH, top - FLAG_HEIGHT / 2.0),
+                Vec2::new(pole_x, top - FLAG_HEIGHT),
+            ],
+            closed: true,
+        })
+        .build()
+}
+
+#[derive(Bundle)]
+pub struct CheckpointBundle {
+    shape_bundle: ShapeBundle,
+    fill: Fill,
+    checkpoint: Checkpoint,
+}
+
+impl CheckpointBundle {
+    pub fn new(x: f32, y: f32) -> Self {
+        CheckpointBundle {
+            shape_bundle: ShapeBundle {
+                path: flag_shape(),
+                transform: Transform::from_xyz(x, y, -1.0),
+                ..default()
+            },
+            fill: Fill::color(INACTIVE_COLOR),
+            checkpoint: Checkpoint {
+                position: Vec2::new(x, y),
+                radius: CHECKPOINT_RADIUS,
+            },
+        }
+    }
+}
+
+pub fn touch_checkpoints(
+    players: Query<(&Player, &Collider, &Transform)>,
+    mut checkpoints: Query<(Entity, &Checkpoint, &Level, &mut Fill)>,
+    mut active_checkpoint: ResMut<ActiveCheckpoint>,
+) {
+    for (player, collider, player_transform) in &players {
+        // don't save a shape that's halfway through being transformed
+        let TransformerAnimState::NotAnimating = player.transformer_anim_state else { continue };
+        // a fused shape falls apart when it dies, so each player goes back to where they saved on their own
+        if !player.fused.is_empty() {
+            continue;
+        }
+        for (checkpoint_entity, checkpoint, level, _) in &checkpoints {
+            let distance = collider.distance_to_point(
+                player_transform.translation.truncate(),
+                0.0,
+                checkpoint.position,
+                true
+            );
+            if distance >= checkpoint.radius {
+                continue;
+            }
+            let already_saved = matches!(
+                &active_checkpoint.0[player.index],
+                Some(save) if save.checkpoint == checkpoint_entity && save.squares == player.squares
+            );
+            if !already_saved {
+                active_checkpoint.0[player.index] = Some(CheckpointSave {
+                    set: level.set,
+                    levelid: level.levelid,
+                    checkpoint: checkpoint_entity,
+                    position: (checkpoint.position.x, checkpoint.position.y),
+                    squares: player.squares.clone(),
+                    carried: player.carried.clone(),
+                });
+            }
+        }
+    }
+
+    if !active_checkpoint.is_changed() {
+        return;
+    }
+    for (checkpoint_entity, _, _, mut fill) in &mut checkpoints {
+        let active = active_checkpoint.0.iter().flatten().any(|save| save.checkpoint == checkpoint_entity);
+        fill.color = if active { ACTIVE_COLOR } else { INACTIVE_COLOR };
+    }
+}
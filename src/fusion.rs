@@ -0,0 +1,123 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use crate::player::{Player, SquarePos, PLAYER_WIDTH, PLAYER_HEIGHT, normalize_squares};
+use crate::rewind::RewindHistory;
+use crate::transformer::{TransformerAnimState, RotateAnimation};
+use crate::LevelTransitioning;
+
+const FUSE_PAD_RADIUS: f32 = 60.0;
+const FUSE_PAD_WIDTH: f32 = 100.0;
+const FUSE_PAD_HEIGHT: f32 = 8.0;
+// how far off the grid, in squares, two players can be from each other and still fuse
+const FUSE_ALIGN_TOLERANCE: f32 = 0.2;
+
+const FUSE_PAD_COLOR: Color = Color::Rgba {
+    red: 0.62890625,
+    green: 0.3984375,
+    blue: 0.8515625,
+    alpha: 1.0,
+};
+
+/// Two players touching each other while both standing on a fuse pad merge into one.
+#[derive(Component)]
+pub struct FusePad {
+    position: Vec2,
+    radius: f32,
+}
+
+#[derive(Bundle)]
+pub struct FusePadBundle {
+    shape_bundle: ShapeBundle,
+    fill: Fill,
+    fuse_pad: FusePad,
+}
+
+impl FusePadBundle {
+    pub fn new(x: f32, y: f32) -> Self {
+        // like a checkpoint, the position is where a square standing on the pad would be
+        let shape = shapes::Rectangle {
+            extents: Vec2::new(FUSE_PAD_WIDTH, FUSE_PAD_HEIGHT),
+            origin: RectangleOrigin::CustomCenter(Vec2::new(0.0, (FUSE_PAD_HEIGHT - PLAYER_HEIGHT) / 2.0)),
+        };
+        FusePadBundle {
+            shape_bundle: ShapeBundle {
+                path: GeometryBuilder::build_as(&shape),
+                transform: Transform::from_xyz(x, y, -1.0),
+                ..default()
+            },
+            fill: Fill::color(FUSE_PAD_COLOR),
+            fuse_pad: FusePad {
+                position: Vec2::new(x, y),
+                radius: FUSE_PAD_RADIUS,
+            },
+        }
+    }
+}
+
+/// The squares of both shapes in the first one's grid, if they sit side by side on the grid without overlapping.
+fn fused_squares(first: &[SquarePos], second: &[SquarePos], offset: IVec2) -> Option<Vec<SquarePos>> {
+    let second: Vec<SquarePos> = second.iter()
+        .map(|square| SquarePos(square.0 + offset.x, square.1 + offset.y))
+        .collect();
+    if second.iter().any(|square| first.contains(square)) {
+        return None;
+    }
+    let touching = first.iter().any(|a| second.iter().any(|b| (a.0 - b.0).abs() + (a.1 - b.1).abs() == 1));
+    if !touching {
+        return None;
+    }
+    Some(first.iter().copied().chain(second).collect())
+}
+
+pub fn fuse_players(
+    mut commands: Commands,
+    pads: Query<&FusePad>,
+    mut players: Query<(Entity, &mut Player, &mut Collider, &mut Path, &mut Transform, &mut RewindHistory)>,
+    level_transitioning: Res<LevelTransitioning>,
+) {
+    if level_transitioning.0 {
+        return;
+    }
+    for pad in &pads {
+        let mut on_pad: Vec<(usize, Entity)> = players.iter()
+            .filter(|(_, player, collider, _, transform, _)| {
+                let distance = collider.distance_to_point(transform.translation.truncate(), 0.0, pad.position, true);
+                matches!(player.transformer_anim_state, TransformerAnimState::NotAnimating) && distance < pad.radius
+            })
+            .map(|(entity, player, ..)| (player.index, entity))
+            .collect();
+        // the player with the lowest index keeps control of the fused shape
+        on_pad.sort();
+        let [(_, first), (_, second), ..] = on_pad[..] else { continue };
+
+        let Ok([
+            (_, mut player, mut collider, mut path, mut transform, mut history),
+            (_, other, _, _, other_transform, _),
+        ]) = players.get_many_mut([first, second]) else { continue };
+        let grid_offset = (other_transform.translation - transform.translation).truncate()
+            / Vec2::new(PLAYER_WIDTH, PLAYER_HEIGHT);
+        if (grid_offset - grid_offset.round()).abs().max_element() > FUSE_ALIGN_TOLERANCE {
+            continue;
+        }
+        let Some(mut squares) = fused_squares(&player.squares, &other.squares, grid_offset.round().as_ivec2()) else {
+            continue;
+        };
+
+        let shift = normalize_squares(&mut squares);
+        player.squares = squares;
+        player.fused.push(other.index);
+        player.fused.extend(other.fused.iter().copied());
+        player.carried.extend(other.carried.iter().copied());
+        transform.translation += (shift.as_vec2() * Vec2::new(PLAYER_WIDTH, PLAYER_HEIGHT)).extend(0.0);
+        *collider = player.get_collider();
+        *path = player.get_shape();
+        // neither undo nor rewind can split the shape apart again
+        player.clear_undo();
+        history.clear();
+        commands.entity(first).remove::<RotateAnimation>();
+        commands.entity(second).despawn();
+        // the despawned player is still in the query until the end of the frame
+        return;
+    }
+}
@@ -0,0 +1,194 @@
+use std::collections::HashSet;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use crate::player::{Player, SquarePos, PLAYER_WIDTH, PLAYER_HEIGHT, normalize_squares};
+use crate::respawn::PlayerDied;
+use crate::rewind::RewindHistory;
+use crate::transformer::RotateAnimation;
+use crate::LevelTransitioning;
+
+const SPIKE_COLOR: Color = Color::Rgba {
+    red: 0.890625,
+    green: 0.23046875,
+    blue: 0.2109375,
+    alpha: 1.0,
+};
+
+const SHREDDER_COLOR: Color = Color::Rgba {
+    red: 0.3515625,
+    green: 0.33984375,
+    blue: 0.37890625,
+    alpha: 1.0,
+};
+
+const SPIKE_WIDTH: f32 = 25.0;
+const STRIPE_SPACING: f32 = 20.0;
+const STRIPE_WIDTH: f32 = 3.0;
+// squares have to overlap a hazard by this much, so that brushing past the edge is safe
+const HAZARD_INSET: f32 = 8.0;
+
+#[derive(Component, Copy, Clone, PartialEq)]
+pub enum Hazard {
+    /// Kills the player on touch.
+    Spikes,
+    /// Cuts off whichever of the player's squares touch it.
+    Shredder,
+}
+
+#[derive(Component)]
+pub struct HazardArea(Rect);
+
+fn spikes_shape(size: Vec2) -> Path {
+    let mut builder = PathBuilder::new();
+    let count = (size.x / SPIKE_WIDTH).round().max(1.0) as i32;
+    let spike_width = size.x / count as f32;
+    builder.move_to(Vec2::new(-size.x / 2.0, -size.y / 2.0));
+    for i in 0..count {
+        let left = -size.x / 2.0 + spike_width * i as f32;
+        builder.line_to(Vec2::new(left + spike_width / 2.0, size.y / 2.0));
+        builder.line_to(Vec2::new(left + spike_width, -size.y / 2.0));
+    }
+    builder.close();
+    builder.build()
+}
+
+fn shredder_stripes(size: Vec2) -> Path {
+    let mut builder = PathBuilder::new();
+    let slant = size.y / 2.0;
+    let mut x = -size.x / 2.0 + STRIPE_SPACING / 2.0;
+    while x + slant < size.x / 2.0 {
+        builder.move_to(Vec2::new(x, -size.y / 2.0));
+        builder.line_to(Vec2::new(x + slant, size.y / 2.0));
+        x += STRIPE_SPACING;
+    }
+    builder.build()
+}
+
+/// Spawns a hazard filling the given area, as a sensor so that the player passes into it.
+pub fn spawn_hazard(commands: &mut Commands, hazard: Hazard, center: Vec2, size: Vec2) -> Entity {
+    let transform = Transform::from_xyz(center.x, center.y, 0.0);
+    let mut entity = match hazard {
+        Hazard::Spikes => commands.spawn((
+            ShapeBundle { path: spikes_shape(size), transform, ..default() },
+            Fill::color(SPIKE_COLOR),
+        )),
+        Hazard::Shredder => {
+            let mut entity = commands.spawn((
+                ShapeBundle {
+                    path: GeometryBuilder::build_as(&shapes::Rectangle { extents: size, ..default() }),
+                    transform,
+                    ..default()
+                },
+                Fill::color(SHREDDER_COLOR),
+            ));
+            entity.with_children(|parent| {
+                parent.spawn((
+                    ShapeBundle {
+                        path: shredder_stripes(size),
+                        transform: Transform::from_xyz(0.0, 0.0, 0.1),
+                        ..default()
+                    },
+                    Stroke::new(SPIKE_COLOR, STRIPE_WIDTH),
+                ));
+            });
+            entity
+        },
+    };
+    entity.insert((
+        Collider::cuboid(size.x / 2.0, size.y / 2.0),
+        Sensor,
+        hazard,
+        HazardArea(Rect::from_center_size(center, size)),
+    ));
+    entity.id()
+}
+
+/// Keeps the biggest group of squares that are still joined together, since a shape can't hold itself
+/// together across a gap.
+fn largest_connected(squares: &[SquarePos]) -> Vec<SquarePos> {
+    let mut unvisited: HashSet<(i32, i32)> = squares.iter().map(|square| (square.0, square.1)).collect();
+    let mut largest = vec![];
+    while let Some(&start) = unvisited.iter().next() {
+        unvisited.remove(&start);
+        let mut group = vec![start];
+        let mut i = 0;
+        while i < group.len() {
+            let (x, y) = group[i];
+            for neighbour in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+                if unvisited.remove(&neighbour) {
+                    group.push(neighbour);
+                }
+            }
+            i += 1;
+        }
+        if group.len() > largest.len() {
+            largest = group;
+        }
+    }
+    // keep the original order so the shape doesn't change when nothing was lost
+    squares.iter().filter(|square| largest.contains(&(square.0, square.1))).copied().collect()
+}
+
+type ShreddableQuery = (
+    Entity,
+    &'static mut Player,
+    &'static mut Collider,
+    &'static mut Path,
+    &'static mut Transform,
+    &'static mut RewindHistory,
+    Option<&'static ColliderDisabled>,
+);
+
+pub fn check_hazards(
+    mut commands: Commands,
+    mut players: Query<ShreddableQuery>,
+    hazards: Query<(&Hazard, &HazardArea), Without<Player>>,
+    mut death_events: EventWriter<PlayerDied>,
+    level_transitioning: Res<LevelTransitioning>,
+) {
+    if level_transitioning.0 {
+        return;
+    }
+    for (entity, mut player, mut collider, mut path, mut transform, mut history, collider_disabled) in &mut players {
+        // safe inside a transformer
+        if collider_disabled.is_some() {
+            continue;
+        }
+        let scale = transform.scale.truncate();
+        let position = transform.translation.truncate();
+        let touches = |square: &SquarePos, kind: Hazard| {
+            let offset = Vec2::new(square.0 as f32 * PLAYER_WIDTH, square.1 as f32 * PLAYER_HEIGHT) * scale;
+            let size = Vec2::new(PLAYER_WIDTH, PLAYER_HEIGHT) * scale - Vec2::splat(HAZARD_INSET * 2.0);
+            let rect = Rect::from_center_size(position + offset, size);
+            hazards.iter().any(|(hazard, area)| *hazard == kind && !rect.intersect(area.0).is_empty())
+        };
+
+        if player.squares.iter().any(|square| touches(square, Hazard::Spikes)) {
+            death_events.send(PlayerDied { player: entity });
+            continue;
+        }
+
+        let remaining: Vec<SquarePos> = player.squares.iter()
+            .filter(|square| !touches(square, Hazard::Shredder))
+            .copied()
+            .collect();
+        if remaining.len() == player.squares.len() {
+            continue;
+        }
+        let mut remaining = largest_connected(&remaining);
+        if remaining.is_empty() {
+            death_events.send(PlayerDied { player: entity });
+            continue;
+        }
+        let shift = normalize_squares(&mut remaining);
+        player.squares = remaining;
+        transform.translation += (shift.as_vec2() * Vec2::new(PLAYER_WIDTH, PLAYER_HEIGHT) * scale).extend(0.0);
+        *collider = player.get_collider();
+        *path = player.get_shape();
+        // the squares are gone for good, so neither undo nor rewind can bring them back
+        player.clear_undo();
+        history.clear();
+        commands.entity(entity).remove::<RotateAnimation>();
+    }
+}
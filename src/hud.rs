@@ -0,0 +1,221 @@
+use bevy::prelude::*;
+use crate::player::{Player, PLAYER_COLOR, SquarePos, get_dimens};
+use crate::transformer::{Transformation, TransformationApplied, TransformationUndone};
+use crate::cave::Cave;
+use crate::respawn::DeathCount;
+
+const HUD_BACKGROUND_COLOR: Color = Color::Rgba {
+    red: 0.14453125,
+    green: 0.125,
+    blue: 0.19140625,
+    alpha: 0.7,
+};
+
+const TARGET_COLOR: Color = Color::Rgba {
+    red: 0.9765625,
+    green: 0.94140625,
+    blue: 0.421875,
+    alpha: 1.0,
+};
+
+// size of one square in the hud diagrams
+const CELL_SIZE: f32 = 12.0;
+const CELL_GAP: f32 = 1.0;
+const FONT_SIZE: f32 = 14.0;
+
+#[derive(Component)]
+pub struct Hud;
+
+#[derive(Copy, Clone, PartialEq)]
+enum DiagramKind {
+    Current,
+    Target,
+}
+
+#[derive(Component)]
+pub struct HudDiagram {
+    kind: DiagramKind,
+    squares: Vec<SquarePos>,
+}
+
+#[derive(Component)]
+pub struct HudDeaths;
+
+#[derive(Component, Default)]
+pub struct HudHistory {
+    transformations: Vec<Transformation>,
+}
+
+fn label(text: &str, text_style: &TextStyle) -> TextBundle {
+    TextBundle::from_section(text, text_style.clone()).with_style(Style {
+        margin: UiRect::right(Val::Px(8.0)),
+        ..default()
+    })
+}
+
+fn diagram_row(parent: &mut ChildBuilder, text: &str, text_style: &TextStyle, kind: DiagramKind) {
+    parent.spawn(NodeBundle {
+        style: Style {
+            align_items: AlignItems::Center,
+            margin: UiRect::bottom(Val::Px(6.0)),
+            ..default()
+        },
+        ..default()
+    }).with_children(|row| {
+        row.spawn(label(text, text_style));
+        row.spawn((
+            NodeBundle::default(),
+            HudDiagram { kind, squares: vec![] },
+        ));
+    });
+}
+
+pub fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let text_style = TextStyle {
+        font: asset_server.load("fonts/bahnschrift.ttf"),
+        font_size: FONT_SIZE,
+        color: Color::WHITE,
+    };
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(60.0),
+                top: Val::Px(60.0),
+                flex_direction: FlexDirection::Column,
+                padding: UiRect::all(Val::Px(8.0)),
+                max_width: Val::Px(260.0),
+                ..default()
+            },
+            background_color: HUD_BACKGROUND_COLOR.into(),
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        Hud,
+    )).with_children(|parent| {
+        diagram_row(parent, "shape", &text_style, DiagramKind::Current);
+        diagram_row(parent, "target", &text_style, DiagramKind::Target);
+        parent.spawn((
+            TextBundle::from_sections([
+                TextSection::new("history: ", text_style.clone()),
+                TextSection::new("", text_style.clone()),
+            ]),
+            HudHistory::default(),
+        ));
+        parent.spawn((
+            TextBundle::from_sections([
+                TextSection::new("deaths: ", text_style.clone()),
+                TextSection::new("0", text_style),
+            ]).with_style(Style {
+                margin: UiRect::top(Val::Px(6.0)),
+                ..default()
+            }),
+            HudDeaths,
+        ));
+    });
+}
+
+pub fn update_hud_visibility(
+    mut huds: Query<&mut Visibility, With<Hud>>,
+    players: Query<(), With<Player>>,
+    caves: Query<&Cave>,
+) {
+    // story levels have no player or an empty cave, so there's nothing to show
+    let show = !players.is_empty() && caves.iter().any(|cave| !cave.squares.is_empty());
+    for mut visibility in &mut huds {
+        *visibility = if show { Visibility::Inherited } else { Visibility::Hidden };
+    }
+}
+
+pub fn update_hud_diagrams(
+    mut commands: Commands,
+    mut diagrams: Query<(Entity, &mut HudDiagram, &mut Style)>,
+    players: Query<&Player>,
+    caves: Query<&Cave>,
+) {
+    for (diagram_entity, mut diagram, mut style) in &mut diagrams {
+        let (squares, color) = match diagram.kind {
+            DiagramKind::Current => (
+                players.iter().find(|player| player.index == 0).map(|player| &player.squares),
+                PLAYER_COLOR,
+            ),
+            DiagramKind::Target => (caves.iter().next().map(|cave| &cave.squares), TARGET_COLOR),
+        };
+        let Some(squares) = squares else { continue };
+        if diagram.squares == *squares {
+            continue;
+        }
+
+        let (w, h) = get_dimens(squares);
+        style.width = Val::Px(w as f32 * CELL_SIZE);
+        style.height = Val::Px(h as f32 * CELL_SIZE);
+        commands.entity(diagram_entity).despawn_descendants().with_children(|parent| {
+            for square in squares {
+                parent.spawn(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(square.0 as f32 * CELL_SIZE),
+                        bottom: Val::Px(square.1 as f32 * CELL_SIZE),
+                        width: Val::Px(CELL_SIZE - CELL_GAP),
+                        height: Val::Px(CELL_SIZE - CELL_GAP),
+                        ..default()
+                    },
+                    background_color: color.into(),
+                    ..default()
+                });
+            }
+        });
+        diagram.squares = squares.clone();
+    }
+}
+
+pub fn update_hud_history(
+    mut transformation_events: EventReader<TransformationApplied>,
+    mut undo_events: EventReader<TransformationUndone>,
+    players: Query<(Entity, &Player)>,
+    new_players: Query<&Player, Added<Player>>,
+    mut histories: Query<(&mut HudHistory, &mut Text)>,
+) {
+    // restarting and switching levels both spawn a fresh player, but so does the other player respawning
+    let restarted = new_players.iter().any(|player| player.index == 0);
+    let shown_player = players.iter().find(|(_, player)| player.index == 0).map(|(entity, _)| entity);
+    let transformations: Vec<Transformation> = transformation_events.iter()
+        .filter(|event| Some(event.player) == shown_player)
+        .map(|event| event.transformation)
+        .collect();
+    let undos = undo_events.iter()
+        .filter(|event| Some(event.player) == shown_player)
+        .count();
+    if !restarted && transformations.is_empty() && undos == 0 {
+        return;
+    }
+
+    for (mut history, mut text) in &mut histories {
+        if restarted {
+            history.transformations.clear();
+        }
+        history.transformations.extend(&transformations);
+        let len = history.transformations.len();
+        history.transformations.truncate(len.saturating_sub(undos));
+        text.sections[1].value = if history.transformations.is_empty() {
+            String::from("(none)")
+        } else {
+            history.transformations.iter()
+                .map(|transformation| transformation.get_name())
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+    }
+}
+
+pub fn update_hud_deaths(
+    death_count: Res<DeathCount>,
+    mut texts: Query<&mut Text, With<HudDeaths>>,
+) {
+    if !death_count.is_changed() {
+        return;
+    }
+    for mut text in &mut texts {
+        text.sections[1].value = death_count.0.to_string();
+    }
+}
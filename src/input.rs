@@ -0,0 +1,332 @@
+use std::collections::{HashMap, HashSet};
+use bevy::prelude::*;
+use serde::{Serialize, Deserialize};
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Restart,
+    Undo,
+    Rewind,
+    Pause,
+}
+
+impl Action {
+    pub const ALL: [Action; 7] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Jump,
+        Action::Restart,
+        Action::Undo,
+        Action::Rewind,
+        Action::Pause,
+    ];
+
+    /// The actions each player has their own bindings for.
+    pub const PER_PLAYER: [Action; 4] = [Action::MoveLeft, Action::MoveRight, Action::Jump, Action::Undo];
+
+    pub fn get_name(&self) -> &str {
+        match self {
+            Action::MoveLeft => "move left",
+            Action::MoveRight => "move right",
+            Action::Jump => "jump",
+            Action::Restart => "restart",
+            Action::Undo => "undo",
+            Action::Rewind => "rewind",
+            Action::Pause => "pause",
+        }
+    }
+}
+
+/// How many players can share the keyboard, each with their own bindings.
+pub const PLAYER_COUNT: usize = 2;
+
+// sticks pushed less than this far count as centred
+pub const STICK_DEADZONE: f32 = 0.3;
+
+// where rebound keys are kept between runs, relative to where the game is run from
+const SETTINGS_PATH: &str = "settings.ron";
+
+/// Which keys and gamepad buttons trigger which actions. Any of an action's inputs will do.
+/// Each player has their own keys; gamepads belong to players in the order they were connected.
+#[derive(Resource, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputBindings {
+    bindings: [HashMap<Action, Vec<KeyCode>>; PLAYER_COUNT],
+    gamepad_bindings: HashMap<Action, Vec<GamepadButtonType>>,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        InputBindings {
+            bindings: [
+                HashMap::from([
+                    (Action::MoveLeft, vec![KeyCode::Left, KeyCode::A]),
+                    (Action::MoveRight, vec![KeyCode::Right, KeyCode::D]),
+                    (Action::Jump, vec![KeyCode::Up, KeyCode::W, KeyCode::Space]),
+                    (Action::Restart, vec![KeyCode::R]),
+                    (Action::Undo, vec![KeyCode::U]),
+                    (Action::Rewind, vec![KeyCode::ShiftLeft, KeyCode::ShiftRight]),
+                    (Action::Pause, vec![KeyCode::Escape]),
+                ]),
+                // the second player only gets the actions that act on a single player
+                HashMap::from([
+                    (Action::MoveLeft, vec![KeyCode::J]),
+                    (Action::MoveRight, vec![KeyCode::L]),
+                    (Action::Jump, vec![KeyCode::I]),
+                    (Action::Undo, vec![KeyCode::O]),
+                ]),
+            ],
+            gamepad_bindings: HashMap::from([
+                (Action::MoveLeft, vec![GamepadButtonType::DPadLeft]),
+                (Action::MoveRight, vec![GamepadButtonType::DPadRight]),
+                (Action::Jump, vec![GamepadButtonType::South]),
+                (Action::Restart, vec![GamepadButtonType::Select]),
+                (Action::Undo, vec![GamepadButtonType::West]),
+                (Action::Rewind, vec![GamepadButtonType::LeftTrigger, GamepadButtonType::RightTrigger]),
+                (Action::Pause, vec![GamepadButtonType::Start]),
+            ]),
+        }
+    }
+}
+
+impl InputBindings {
+    /// The bindings saved by the last run, or the defaults if there aren't any.
+    pub fn load() -> Self {
+        let Ok(contents) = std::fs::read_to_string(SETTINGS_PATH) else { return default() };
+        ron::from_str(&contents).unwrap_or_else(|err| {
+            warn!("couldn't read {SETTINGS_PATH}, using the default bindings: {err}");
+            default()
+        })
+    }
+
+    /// Writes the bindings out so that the next run starts with them. The browser build has no
+    /// file system to write to, so there they only last until the page is closed.
+    pub fn save(&self) {
+        if cfg!(target_arch = "wasm32") {
+            return;
+        }
+        let result = ron::ser::to_string_pretty(self, default())
+            .map_err(|err| err.to_string())
+            .and_then(|contents| std::fs::write(SETTINGS_PATH, contents).map_err(|err| err.to_string()));
+        if let Err(err) = result {
+            warn!("couldn't save the bindings to {SETTINGS_PATH}: {err}");
+        }
+    }
+
+    pub fn get_keys(&self, player: usize, action: Action) -> &[KeyCode] {
+        self.bindings[player].get(&action).map_or(&[], |keys| keys.as_slice())
+    }
+
+    pub fn get_gamepad_buttons(&self, action: Action) -> &[GamepadButtonType] {
+        self.gamepad_bindings.get(&action).map_or(&[], |buttons| buttons.as_slice())
+    }
+
+    /// Replaces the action's main key, keeping any alternatives.
+    pub fn rebind(&mut self, player: usize, action: Action, key: KeyCode) {
+        let keys = self.bindings[player].entry(action).or_default();
+        keys.retain(|&other| other != key);
+        if keys.is_empty() {
+            keys.push(key);
+        } else {
+            keys[0] = key;
+        }
+    }
+
+    pub fn describe(&self, player: usize, action: Action) -> String {
+        self.get_keys(player, action).iter()
+            .map(|key| format!("{:?}", key))
+            .collect::<Vec<_>>()
+            .join(" / ")
+    }
+}
+
+/// The actions each player is holding down this frame and last frame, from every input source.
+#[derive(Resource, Default)]
+pub struct ActionState {
+    held: HashSet<(usize, Action)>,
+    previous: HashSet<(usize, Action)>,
+}
+
+impl ActionState {
+    pub fn press(&mut self, player: usize, action: Action) {
+        self.held.insert((player, action));
+    }
+
+    pub fn player_pressed(&self, player: usize, action: Action) -> bool {
+        self.held.contains(&(player, action))
+    }
+
+    pub fn player_just_pressed(&self, player: usize, action: Action) -> bool {
+        self.held.contains(&(player, action)) && !self.previous.contains(&(player, action))
+    }
+
+    pub fn player_just_released(&self, player: usize, action: Action) -> bool {
+        !self.held.contains(&(player, action)) && self.previous.contains(&(player, action))
+    }
+
+    /// Whether any player is holding the action.
+    pub fn pressed(&self, action: Action) -> bool {
+        (0..PLAYER_COUNT).any(|player| self.player_pressed(player, action))
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        (0..PLAYER_COUNT).any(|player| self.player_just_pressed(player, action))
+    }
+
+    pub fn just_released(&self, action: Action) -> bool {
+        (0..PLAYER_COUNT).any(|player| self.player_just_released(player, action))
+    }
+}
+
+/// Starts a new frame of input; input sources add their actions after this runs.
+pub fn clear_actions(mut action_state: ResMut<ActionState>) {
+    let action_state = &mut *action_state;
+    action_state.previous = std::mem::take(&mut action_state.held);
+}
+
+pub fn read_keyboard_actions(
+    keyboard_input: Res<Input<KeyCode>>,
+    bindings: Res<InputBindings>,
+    mut action_state: ResMut<ActionState>,
+) {
+    for player in 0..PLAYER_COUNT {
+        for action in Action::ALL {
+            if keyboard_input.any_pressed(bindings.get_keys(player, action).iter().copied()) {
+                action_state.press(player, action);
+            }
+        }
+    }
+}
+
+pub fn read_gamepad_actions(
+    gamepads: Res<Gamepads>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    bindings: Res<InputBindings>,
+    mut action_state: ResMut<ActionState>,
+) {
+    // bevy keeps the gamepads in a map, so they're sorted to keep each one with the same player every frame
+    let mut connected: Vec<Gamepad> = gamepads.iter().collect();
+    connected.sort_by_key(|gamepad| gamepad.id);
+    for (player, gamepad) in connected.into_iter().enumerate().take(PLAYER_COUNT) {
+        for action in Action::ALL {
+            let buttons = bindings.get_gamepad_buttons(action).iter()
+                .map(|&button_type| GamepadButton::new(gamepad, button_type));
+            if gamepad_input.any_pressed(buttons) {
+                action_state.press(player, action);
+            }
+        }
+
+        let stick_x = axes.get(GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX)).unwrap_or(0.0);
+        if stick_x <= -STICK_DEADZONE {
+            action_state.press(player, Action::MoveLeft);
+        } else if stick_x >= STICK_DEADZONE {
+            action_state.press(player, Action::MoveRight);
+        }
+    }
+}
+
+/// Headless gamepads for tests, which are pressed by hand instead of read from real devices.
+#[cfg(test)]
+pub mod test_gamepads {
+    use bevy::prelude::*;
+    use bevy::input::gamepad::{gamepad_connection_system, GamepadConnection, GamepadConnectionEvent, GamepadInfo};
+
+    /// An app with just the gamepad input resources, and bevy's system for connecting gamepads.
+    pub fn gamepad_input_app() -> App {
+        let mut app = App::new();
+        app.add_event::<GamepadConnectionEvent>()
+            .init_resource::<Gamepads>()
+            .init_resource::<Input<GamepadButton>>()
+            .init_resource::<Axis<GamepadAxis>>()
+            .init_resource::<Axis<GamepadButton>>()
+            .add_systems(PreUpdate, gamepad_connection_system);
+        app
+    }
+
+    pub fn connect_gamepad(app: &mut App, id: usize) -> Gamepad {
+        let gamepad = Gamepad::new(id);
+        let info = GamepadInfo { name: "test pad".to_string() };
+        app.world.send_event(GamepadConnectionEvent::new(gamepad, GamepadConnection::Connected(info)));
+        app.update();
+        gamepad
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_gamepads::{gamepad_input_app, connect_gamepad};
+    use super::*;
+
+    fn gamepad_app() -> App {
+        let mut app = gamepad_input_app();
+        app.init_resource::<InputBindings>()
+            .init_resource::<ActionState>()
+            .add_systems(Update, (clear_actions, read_gamepad_actions).chain());
+        app
+    }
+
+    #[test]
+    fn gamepad_buttons_press_actions() {
+        let mut app = gamepad_app();
+        let gamepad = connect_gamepad(&mut app, 0);
+
+        app.world.resource_mut::<Input<GamepadButton>>().press(GamepadButton::new(gamepad, GamepadButtonType::South));
+        app.update();
+        let action_state = app.world.resource::<ActionState>();
+        assert!(action_state.player_just_pressed(0, Action::Jump));
+        assert!(!action_state.player_pressed(1, Action::Jump));
+
+        app.update();
+        assert!(app.world.resource::<ActionState>().player_pressed(0, Action::Jump));
+        assert!(!app.world.resource::<ActionState>().player_just_pressed(0, Action::Jump));
+
+        app.world.resource_mut::<Input<GamepadButton>>().release(GamepadButton::new(gamepad, GamepadButtonType::South));
+        app.update();
+        assert!(app.world.resource::<ActionState>().player_just_released(0, Action::Jump));
+    }
+
+    #[test]
+    fn left_stick_moves_past_the_deadzone() {
+        let mut app = gamepad_app();
+        let gamepad = connect_gamepad(&mut app, 0);
+        let stick_x = GamepadAxis::new(gamepad, GamepadAxisType::LeftStickX);
+
+        app.world.resource_mut::<Axis<GamepadAxis>>().set(stick_x, STICK_DEADZONE / 2.0);
+        app.update();
+        assert!(!app.world.resource::<ActionState>().player_pressed(0, Action::MoveRight));
+
+        app.world.resource_mut::<Axis<GamepadAxis>>().set(stick_x, -0.8);
+        app.update();
+        let action_state = app.world.resource::<ActionState>();
+        assert!(action_state.player_pressed(0, Action::MoveLeft));
+        assert!(!action_state.player_pressed(0, Action::MoveRight));
+    }
+
+    #[test]
+    fn second_gamepad_controls_second_player() {
+        let mut app = gamepad_app();
+        connect_gamepad(&mut app, 0);
+        let second = connect_gamepad(&mut app, 1);
+
+        app.world.resource_mut::<Input<GamepadButton>>().press(GamepadButton::new(second, GamepadButtonType::DPadRight));
+        app.update();
+        let action_state = app.world.resource::<ActionState>();
+        assert!(action_state.player_pressed(1, Action::MoveRight));
+        assert!(!action_state.player_pressed(0, Action::MoveRight));
+    }
+
+    #[test]
+    fn rebound_keys_survive_saving() {
+        let mut bindings = InputBindings::default();
+        bindings.rebind(1, Action::Jump, KeyCode::K);
+        let saved = ron::ser::to_string_pretty(&bindings, default()).unwrap();
+        let loaded: InputBindings = ron::from_str(&saved).unwrap();
+        assert_eq!(loaded.get_keys(1, Action::Jump), &[KeyCode::K]);
+        assert_eq!(loaded.get_keys(0, Action::Jump), bindings.get_keys(0, Action::Jump));
+        assert_eq!(loaded.get_gamepad_buttons(Action::Jump), &[GamepadButtonType::South]);
+    }
+}
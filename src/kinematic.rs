@@ -0,0 +1,129 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use crate::player::{Player, Grounded, MovementSettings, MovementBackend, PLAYER_HEIGHT};
+use crate::surface::{BlockKind, ONE_WAY_GROUP};
+use crate::transformer::TransformerAnimState;
+use crate::rewind::Rewinding;
+use crate::LevelTransitioning;
+
+// slopes steeper than this are treated as walls
+const MAX_SLOPE_CLIMB_ANGLE: f32 = std::f32::consts::FRAC_PI_4;
+// a quarter of a square, so that small ledges don't stop the player
+const MAX_STEP_HEIGHT: f32 = PLAYER_HEIGHT / 4.0;
+const SNAP_TO_GROUND_DISTANCE: f32 = 4.0;
+
+fn character_controller() -> KinematicCharacterController {
+    KinematicCharacterController {
+        max_slope_climb_angle: MAX_SLOPE_CLIMB_ANGLE,
+        autostep: Some(CharacterAutostep {
+            max_height: CharacterLength::Absolute(MAX_STEP_HEIGHT),
+            min_width: CharacterLength::Absolute(1.0),
+            include_dynamic_bodies: false,
+        }),
+        snap_to_ground: Some(CharacterLength::Absolute(SNAP_TO_GROUND_DISTANCE)),
+        ..default()
+    }
+}
+
+/// Swaps the player's rigid body between the dynamic and kinematic backends whenever a player
+/// spawns or the setting changes.
+pub fn apply_movement_backend(
+    mut commands: Commands,
+    settings: Res<MovementSettings>,
+    players: Query<Entity, With<Player>>,
+    new_players: Query<Entity, Added<Player>>,
+) {
+    let changed_players: Vec<Entity> = if settings.is_changed() {
+        players.iter().collect()
+    } else {
+        new_players.iter().collect()
+    };
+    for entity in changed_players {
+        match settings.backend {
+            MovementBackend::Dynamic => {
+                commands.entity(entity)
+                    .insert(RigidBody::Dynamic)
+                    .remove::<(KinematicCharacterController, KinematicCharacterControllerOutput)>();
+            },
+            MovementBackend::Kinematic => {
+                commands.entity(entity).insert((RigidBody::KinematicPositionBased, character_controller()));
+            },
+        }
+    }
+}
+
+type KinematicPlayerQuery = (
+    &'static Player,
+    &'static mut Velocity,
+    &'static mut Transform,
+    &'static mut KinematicCharacterController,
+    Option<&'static KinematicCharacterControllerOutput>,
+    &'static GravityScale,
+    &'static Grounded,
+    Option<&'static ColliderDisabled>,
+);
+
+/// Turns the velocity set by `move_player` into character controller movement, since rapier
+/// doesn't apply gravity, velocity or bounciness to kinematic bodies itself.
+pub fn move_kinematic_player(
+    mut player_info: Query<KinematicPlayerQuery>,
+    block_kinds: Query<&BlockKind>,
+    rapier_config: Res<RapierConfiguration>,
+    settings: Res<MovementSettings>,
+    level_transitioning: Res<LevelTransitioning>,
+    rewinding: Res<Rewinding>,
+    time: Res<Time>,
+) {
+    let dt = time.delta_seconds();
+    for (player, mut velocity, mut transform, mut controller, output, gravity_scale, grounded, collider_disabled) in &mut player_info {
+        if level_transitioning.0 || rewinding.0 {
+            controller.translation = None;
+            continue;
+        }
+        if collider_disabled.is_some() {
+            // being moved by a transformer, which shouldn't be blocked by anything
+            controller.translation = None;
+            if let TransformerAnimState::MovingAway { ballistic: true, .. } = player.transformer_anim_state {
+                velocity.linvel.y += rapier_config.gravity.y * gravity_scale.0 * dt;
+            }
+            transform.translation += (velocity.linvel * dt).extend(0.0);
+            continue;
+        }
+
+        if let Some(output) = output {
+            if output.grounded && velocity.linvel.y < 0.0 {
+                let restitution = grounded.0
+                    .and_then(|ground| block_kinds.get(ground).ok())
+                    .map_or(0.0, |kind| kind.surface().1.coefficient);
+                velocity.linvel.y *= -restitution;
+            }
+        }
+        velocity.linvel.y += rapier_config.gravity.y * gravity_scale.0 * dt;
+        velocity.linvel.y = velocity.linvel.y.max(-settings.max_fall_speed);
+        controller.translation = Some(velocity.linvel * dt);
+    }
+}
+
+/// Lets the character controller through one-way blocks while the player is moving up or still partway
+/// inside one, since it doesn't run the physics hooks that do this for the dynamic backend.
+pub fn pass_through_one_way_blocks(
+    rapier_context: Res<RapierContext>,
+    mut players: Query<(Entity, &Velocity, &Transform, &Collider, &mut KinematicCharacterController)>,
+    block_kinds: Query<&BlockKind>,
+) {
+    let is_one_way = |block| block_kinds.get(block) == Ok(&BlockKind::OneWay);
+    for (entity, velocity, transform, collider, mut controller) in &mut players {
+        let filter = QueryFilter::default().exclude_collider(entity).exclude_sensors().predicate(&is_one_way);
+        let inside = rapier_context.intersection_with_shape(
+            transform.translation.truncate(),
+            0.0,
+            collider,
+            filter,
+        ).is_some();
+        controller.filter_groups = if velocity.linvel.y > 0.0 || inside {
+            Some(CollisionGroups::new(Group::ALL, Group::ALL - ONE_WAY_GROUP))
+        } else {
+            None
+        };
+    }
+}
@@ -2,13 +2,54 @@ mod player;
 mod map;
 mod transformer;
 mod cave;
+mod outline;
+mod hud;
+mod rewind;
+mod kinematic;
+mod tween;
+mod trajectory;
+mod respawn;
+mod checkpoint;
+mod input;
+mod menu;
+mod touch;
+mod fusion;
+mod pickup;
+mod moving;
+mod hazard;
+mod surface;
+mod plate;
 
-use bevy::prelude::*;
+use bevy::{prelude::*, input::InputSystem, ui::UiSystem};
 use bevy_rapier2d::prelude::*;
 use bevy_prototype_lyon::prelude::*;
-use player::{Player, spawn_player, spawn_player_at_point, move_player};
-use transformer::apply_transformations;
-use map::{spawn_map, next_level, Level, get_levels, start_level, button_system};
+use player::{
+    Player, MovementSettings, spawn_player, spawn_player_at_point, respawn_player_at_point, move_player,
+    update_grounded, ShapeChange,
+};
+use checkpoint::{ActiveCheckpoint, touch_checkpoints};
+use transformer::{
+    apply_transformations, tick_transformer_cooldowns, follow_capturing_transformers, preview_transformations,
+    animate_rotations, TransformerAnimState, TransformationApplied, TransformationUndone, RotateAnimation,
+};
+use cave::{CaveHintsEnabled, toggle_cave_hints, update_cave_hints};
+use hud::{spawn_hud, update_hud_visibility, update_hud_diagrams, update_hud_history, update_hud_deaths};
+use respawn::{PlayerDied, DeathCount, check_out_of_bounds, respawn_dead_players};
+use rewind::{Rewinding, record_or_rewind};
+use kinematic::{apply_movement_backend, move_kinematic_player, pass_through_one_way_blocks};
+use trajectory::{TrajectoryOverlayEnabled, toggle_trajectory_overlay, update_trajectory_overlay};
+use input::{Action, ActionState, InputBindings, clear_actions, read_keyboard_actions, read_gamepad_actions};
+use menu::{
+    Paused, Rebinding, GamepadFocus, game_running, navigate_buttons_with_gamepad, toggle_pause, pause_menu_buttons, capture_rebind, update_binding_texts,
+};
+use touch::{TouchControlsEnabled, spawn_touch_controls, enable_touch_controls, read_touch_actions};
+use fusion::fuse_players;
+use moving::{move_along_paths, carry_players};
+use hazard::check_hazards;
+use surface::OneWayPlatformHooks;
+use plate::{press_plates, update_gates};
+use pickup::{attach_loose_squares, return_loose_squares};
+use map::{spawn_map, next_level, Level, LevelSet, start_level, button_system};
 
 const WINDOW_WIDTH: f32 = 1200.0;
 const WINDOW_HEIGHT: f32 = 800.0;
@@ -20,6 +61,8 @@ pub struct LevelTransitioning(bool);
 pub struct FadeToBlack {
     timer: Timer,
     switched_level: bool,
+    /// The set of levels and the level in it to switch to.
+    destination: (LevelSet, usize),
 }
 
 const BLACK: Color = Color::Rgba {
@@ -33,7 +76,6 @@ fn fade_step(
     mut commands: Commands,
     mut fade_to_blacks: Query<(Entity, &mut Sprite, &mut FadeToBlack)>,
     asset_server: Res<AssetServer>,
-    levels: Query<&Level>,
     level_entities: Query<Entity, With<Level>>,
     player_entities: Query<Entity, With<Player>>,
     mut level_transitioning: ResMut<LevelTransitioning>,
@@ -51,17 +93,21 @@ fn fade_step(
         }
 
         if fade_to_black.timer.percent() > 0.4 && !fade_to_black.switched_level {
-            let current_level = levels.iter().next().unwrap().levelid;
+            let (set, levelid) = fade_to_black.destination;
             fade_to_black.switched_level = true;
             for entity in &level_entities {
                 commands.entity(entity).despawn();
             }
-            start_level(&mut commands, &asset_server, current_level + 1);
+            start_level(&mut commands, &asset_server, set, levelid);
             for entity in &player_entities {
                 commands.entity(entity).despawn();
             }
-            if current_level != 0 {
-                spawn_player_at_point(&mut commands, get_levels()[current_level + 1].spawn_point);
+            // levels without caves are just something to read, so there's no one to play as
+            let level_data = &set.levels()[levelid];
+            if !level_data.caves.is_empty() {
+                for (index, spawn_point) in level_data.spawn_points().into_iter().enumerate() {
+                    spawn_player_at_point(&mut commands, spawn_point, index);
+                }
             }
         }
 
@@ -72,7 +118,7 @@ fn fade_step(
     }
 }
 
-pub fn spawn_fade_to_black(commands: &mut Commands) {
+pub fn spawn_fade_to_black(commands: &mut Commands, destination: (LevelSet, usize)) {
     commands.spawn((
         SpriteBundle {
             sprite: Sprite {
@@ -86,6 +132,7 @@ pub fn spawn_fade_to_black(commands: &mut Commands) {
         FadeToBlack {
             timer: Timer::from_seconds(1., TimerMode::Once),
             switched_level: false,
+            destination,
         },
     ));
 }
@@ -105,32 +152,131 @@ fn setup(
 
 fn check_restart(
     mut commands: Commands,
-    keyboard_input: Res<Input<KeyCode>>,
+    action_state: Res<ActionState>,
     player_entities: Query<Entity, With<Player>>,
     levels: Query<&Level>,
+    active_checkpoint: Res<ActiveCheckpoint>,
 ) {
-    if keyboard_input.just_released(KeyCode::R) {
-        let spawn_point = levels.iter().next().unwrap().spawn_point;
+    if action_state.just_released(Action::Restart) {
+        let level = levels.iter().next().unwrap();
         for entity in &player_entities {
             commands.entity(entity).despawn();
         }
-        spawn_player_at_point(&mut commands, spawn_point);
+        for index in 0..level.player_count() {
+            match active_checkpoint.respawn_for(level, index) {
+                Some((checkpoint_point, squares, carried)) => {
+                    respawn_player_at_point(&mut commands, checkpoint_point, squares, carried, index)
+                },
+                None => spawn_player_at_point(&mut commands, level.spawn_point_for(index), index),
+            }
+        }
+    }
+}
+
+fn check_undo(
+    mut commands: Commands,
+    action_state: Res<ActionState>,
+    mut player_info: Query<(Entity, &mut Player, &mut Collider, &mut Path, &mut Transform, &mut Velocity)>,
+    mut undo_events: EventWriter<TransformationUndone>,
+    level_transitioning: Res<LevelTransitioning>,
+) {
+    if level_transitioning.0 || !action_state.just_pressed(Action::Undo) {
+        return;
+    }
+    for (entity, mut player, mut collider, mut path, mut transform, mut velocity) in &mut player_info {
+        if !action_state.player_just_pressed(player.index, Action::Undo) {
+            continue;
+        }
+        // don't yank the player out of a transformer halfway through
+        if let TransformerAnimState::MovingToward { .. } | TransformerAnimState::MovingAway { .. } = player.transformer_anim_state {
+            continue;
+        }
+        if let Some((position, change)) = player.pop_undo() {
+            *path = player.get_shape();
+            *collider = player.get_collider();
+            transform.translation = position;
+            transform.scale = Vec3::ONE;
+            velocity.linvel = Vec2::ZERO;
+            commands.entity(entity).remove::<(ColliderDisabled, RotateAnimation)>();
+            if change == ShapeChange::Transformation {
+                undo_events.send(TransformationUndone { player: entity });
+            }
+        }
     }
 }
 
 fn main() {
     App::new()
-        .add_systems(Startup, (setup, spawn_player, spawn_map))
+        .add_systems(Startup, (setup, spawn_player, spawn_map, spawn_hud, spawn_touch_controls))
+        .add_systems(PreUpdate, (
+            (
+                clear_actions,
+                enable_touch_controls,
+                (read_keyboard_actions, read_gamepad_actions, read_touch_actions),
+            ).chain().after(InputSystem),
+            navigate_buttons_with_gamepad.after(UiSystem::Focus),
+        ))
         .add_systems(Update, (
+            update_grounded.before(move_player),
             move_player,
-            player::set_jumping_false,
             apply_transformations,
+            preview_transformations,
+            animate_rotations.after(apply_transformations),
             check_restart,
+            check_undo,
             next_level,
             fade_step,
             button_system,
+            apply_movement_backend,
+            move_kinematic_player.after(move_player),
+            pass_through_one_way_blocks.after(move_kinematic_player),
+            check_out_of_bounds,
+            touch_checkpoints,
+            respawn_dead_players.after(check_out_of_bounds),
+            fuse_players,
+            attach_loose_squares,
+            return_loose_squares,
+        ).run_if(game_running))
+        .add_systems(Update, (
+            move_along_paths,
+            carry_players.after(move_along_paths).after(update_grounded),
+            check_hazards.before(respawn_dead_players),
+            tick_transformer_cooldowns.before(apply_transformations),
+            follow_capturing_transformers.after(move_along_paths).before(move_player),
+            press_plates.after(update_grounded),
+            update_gates.after(press_plates),
+        ).run_if(game_running))
+        .add_systems(Update, (
+            toggle_cave_hints,
+            update_cave_hints,
+            update_hud_visibility,
+            update_hud_diagrams,
+            update_hud_history,
+            update_hud_deaths,
+            toggle_trajectory_overlay,
+            update_trajectory_overlay,
+            toggle_pause.before(capture_rebind),
+            pause_menu_buttons,
+            capture_rebind,
+            update_binding_texts.after(capture_rebind),
         ))
+        .add_systems(FixedUpdate, record_or_rewind.run_if(game_running))
         .insert_resource(LevelTransitioning(false))
+        .insert_resource(CaveHintsEnabled(true))
+        .insert_resource(Rewinding(false))
+        .insert_resource(TrajectoryOverlayEnabled(false))
+        .insert_resource(Paused(false))
+        .insert_resource(TouchControlsEnabled(false))
+        .init_resource::<Rebinding>()
+        .init_resource::<GamepadFocus>()
+        .insert_resource(InputBindings::load())
+        .init_resource::<ActionState>()
+        .init_resource::<MovementSettings>()
+        .add_event::<TransformationApplied>()
+        .add_event::<TransformationUndone>()
+        .add_event::<PlayerDied>()
+        .init_resource::<DeathCount>()
+        .init_resource::<ActiveCheckpoint>()
         .add_plugins((
             DefaultPlugins.set(WindowPlugin {
                 primary_window: Some(Window {
@@ -141,7 +287,7 @@ fn main() {
                 ..default()
             }),
             ShapePlugin,
-            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0),
+            RapierPhysicsPlugin::<OneWayPlatformHooks>::pixels_per_meter(100.0),
         ))
         .run();
 }
@@ -4,29 +4,81 @@ use bevy::{
 };
 use bevy_rapier2d::prelude::*;
 use crate::player::{Player, PLAYER_WIDTH, PLAYER_HEIGHT, SquarePos};
-use crate::transformer::{TransformerBundle, Transformation};
+use crate::transformer::{TransformerBundle, Transformation, Ejection};
 use crate::cave::{Cave, CaveBundle};
-use crate::{spawn_fade_to_black, LevelTransitioning};
+use crate::checkpoint::{ActiveCheckpoint, CheckpointBundle};
+use crate::fusion::FusePadBundle;
+use crate::pickup::LooseSquareBundle;
+use crate::moving::{FollowPath, Waypoints, PathMode};
+use crate::hazard::{Hazard, spawn_hazard};
+use crate::surface::BlockKind;
+use crate::plate::{PlateRequirement, GateKind, spawn_pressure_plate, spawn_gate};
+use crate::{spawn_fade_to_black, LevelTransitioning, WINDOW_WIDTH, WINDOW_HEIGHT};
 
-const BUTTON_COLOR: Color = Color::Rgba {
+pub const BUTTON_COLOR: Color = Color::Rgba {
     red: 0.60546875,
     green: 0.6015625,
     blue: 0.58984375,
     alpha: 1.0,
 };
 
-const BUTTON_COLOR_HOVER: Color = Color::Rgba {
+pub const BUTTON_COLOR_HOVER: Color = Color::Rgba {
     red: 0.75390625,
     green: 0.75,
     blue: 0.73828125,
     alpha: 1.0,
 };
 
+/// Which list of levels is being played. The story is played first, and the others can be picked instead
+/// from the screen after the title.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum LevelSet {
+    Story,
+    /// Levels for two players at once.
+    Coop,
+    /// Levels that show off one thing each, outside of the story.
+    Showcase,
+}
+
+impl LevelSet {
+    pub const ALL: [LevelSet; 3] = [LevelSet::Story, LevelSet::Coop, LevelSet::Showcase];
+
+    pub fn levels(&self) -> Vec<LevelData> {
+        match self {
+            LevelSet::Story => get_levels(),
+            LevelSet::Coop => get_coop_levels(),
+            LevelSet::Showcase => get_showcase_levels(),
+        }
+    }
+
+    fn button_label(&self) -> &str {
+        match self {
+            LevelSet::Story => "(continue)",
+            LevelSet::Coop => "(co-op levels)",
+            LevelSet::Showcase => "(extras)",
+        }
+    }
+}
+
+/// Which level comes after this one. Every set leads back to the title once it's finished.
+pub fn level_after(set: LevelSet, levelid: usize) -> (LevelSet, usize) {
+    if levelid + 1 < set.levels().len() {
+        (set, levelid + 1)
+    } else {
+        (LevelSet::Story, 0)
+    }
+}
+
+/// The button that takes the player on to the next level, or to the start of another set of levels.
+#[derive(Component)]
+pub struct ContinueButton(pub LevelSet);
+
 struct Block {
     x: f32,
     y: f32,
     w: f32,
     h: f32,
+    kind: BlockKind,
 }
 
 impl Block {
@@ -41,7 +93,7 @@ impl Block {
     fn to_sprite_bundle(&self) -> SpriteBundle {
         SpriteBundle {
             sprite: Sprite {
-                color: Color::rgb(1.0, 1.0, 1.0),
+                color: self.kind.get_color(),
                 custom_size: Some(Vec2::new(self.w, self.h)),
                 ..default()
             },
@@ -57,8 +109,26 @@ impl Block {
 
 #[derive(Component, Copy, Clone)]
 pub struct Level {
+    pub set: LevelSet,
     pub levelid: usize,
     pub spawn_point: (f32, f32),
+    pub coop_spawn_point: Option<(f32, f32)>,
+    /// The player dies and respawns when they leave this area.
+    pub kill_bounds: Rect,
+}
+
+impl Level {
+    pub fn spawn_point_for(&self, index: usize) -> (f32, f32) {
+        match index {
+            0 => self.spawn_point,
+            _ => self.coop_spawn_point.unwrap_or(self.spawn_point),
+        }
+    }
+
+    /// How many players this level is played with.
+    pub fn player_count(&self) -> usize {
+        if self.coop_spawn_point.is_some() { 2 } else { 1 }
+    }
 }
 
 struct TextBlock {
@@ -68,41 +138,65 @@ struct TextBlock {
     min_width: Option<f32>,
 }
 
+#[derive(Default)]
 pub struct LevelData {
     blocks: Vec<Block>,
-    transformers: Vec<(f32, f32, Transformation, Vec2)>,
-    cave: Cave,
+    moving_blocks: Vec<(Block, Waypoints)>,
+    hazards: Vec<(Block, Hazard)>,
+    transformers: Vec<(f32, f32, Transformation, Ejection)>,
+    moving_transformers: Vec<(f32, f32, Transformation, Ejection, Waypoints)>,
+    checkpoints: Vec<(f32, f32)>,
+    fuse_pads: Vec<(f32, f32)>,
+    loose_squares: Vec<(f32, f32)>,
+    /// Each plate's position, what it takes to press it, and the link of the gates it powers.
+    pressure_plates: Vec<(f32, f32, PlateRequirement, usize)>,
+    gates: Vec<(Block, GateKind, usize)>,
+    pub caves: Vec<Cave>,
     background: Option<String>,
     pub spawn_point: (f32, f32),
+    /// Where the second player starts, in levels played together.
+    pub coop_spawn_point: Option<(f32, f32)>,
+    // defaults to the window
+    kill_bounds: Option<Rect>,
+    /// Where the buttons for going on, or for picking another set of levels, go.
     button_pos: Option<(f32, f32)>,
     text_blocks: Vec<TextBlock>,
 }
 
+impl LevelData {
+    /// Where each player starts, in order.
+    pub fn spawn_points(&self) -> Vec<(f32, f32)> {
+        std::iter::once(self.spawn_point).chain(self.coop_spawn_point).collect()
+    }
+}
+
 pub fn get_levels() -> Vec<LevelData> {
     vec![
         LevelData {
             blocks: vec![
                 // frame
-                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0 },
-                Block { x: -600.0, y: -350.0, w: 1200.0, h: 50.0 },
-                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0 },
-                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0 },
+                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: -350.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
                 // pole
-                Block { x: 300.0, y: -100.0, w: 50.0, h: 250.0 },
+                Block { x: 300.0, y: -100.0, w: 50.0, h: 250.0, kind: BlockKind::Solid },
                 // misc platforms
-                Block { x: -100.0, y: -250.0, w: 100.0, h: 50.0 },
-                Block { x: -250.0, y: -200.0, w: 100.0, h: 50.0 },
-                Block { x: -450.0, y: -100.0, w: 250.0, h: 50.0 },
-                Block { x: -450.0, y: -50.0, w: 50.0, h: 50.0 },
-                Block { x: -350.0, y: 50.0, w: 50.0, h: 50.0 },
-                Block { x: -300.0, y: 150.0, w: 50.0, h: 50.0 },
-                Block { x: -250.0, y: 250.0, w: 200.0, h: 50.0 },
+                Block { x: -100.0, y: -250.0, w: 100.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -250.0, y: -200.0, w: 100.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -450.0, y: -100.0, w: 250.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -450.0, y: -50.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -350.0, y: 50.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -300.0, y: 150.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -250.0, y: 250.0, w: 200.0, h: 50.0, kind: BlockKind::Solid },
             ],
             transformers: vec![],
-            cave: Cave {
-                position: Vec2::new(500.0, -250.0),
-                squares: vec![SquarePos(0, 0)],
-            },
+            caves: vec![
+                Cave {
+                    position: Vec2::new(500.0, -250.0),
+                    squares: vec![SquarePos(0, 0)],
+                },
+            ],
             background: Some(String::from("backgrounds/level0.png")),
             spawn_point: (-550.0, -200.0),
             button_pos: None,
@@ -120,18 +214,17 @@ pub fn get_levels() -> Vec<LevelData> {
                     min_width: None,
                 },
             ],
+            ..default()
         },
         LevelData {
             blocks: vec![
-                Block { x: -600.0, y: 550.0, w: 1200.0, h: 50.0 },
+                Block { x: -600.0, y: 550.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
             ],
             transformers: vec![],
-            cave: Cave {
-                position: Vec2::new(500.0, 500.0),
-                squares: vec![],
-            },
+            caves: vec![],
             background: Some(String::from("backgrounds/level1.png")),
             spawn_point: (-550.0, -500.0),
+            kill_bounds: Some(Rect::new(-600.0, -600.0, 600.0, 600.0)),
             button_pos: Some((200.0, 200.0)),
             text_blocks: vec![
                 TextBlock {
@@ -145,216 +238,240 @@ I can't afford to change my shape. I won't be able to fit into my caves anymore.
                     min_width: Some(500.0),
                 },
             ],
+            ..default()
         },
         LevelData {
             blocks: vec![
                 // frame
-                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0 },
-                Block { x: -600.0, y: -250.0, w: 1200.0, h: 150.0 },
-                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0 },
-                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0 },
+                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: -250.0, w: 1200.0, h: 150.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
                 // climbing blocks
-                Block { x: -150.0, y: -150.0, w: 50.0, h: 50.0 },
-                Block { x: -100.0, y: -50.0, w: 50.0, h: 50.0 },
-                Block { x: -50.0, y: 50.0, w: 50.0, h: 50.0 },
+                Block { x: -150.0, y: -150.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -100.0, y: -50.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -50.0, y: 50.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
             ],
             transformers: vec![
-                (-350.0, -225.0, Transformation::AddRight, Vec2::new(200.0, 200.0)),
-                (-50.0, -225.0, Transformation::AddTop, Vec2::new(200.0, 200.0)),
-                (-25.0, 75.0, Transformation::RotateCw, Vec2::new(200.0, 200.0)),
+                (-350.0, -225.0, Transformation::AddRight, Ejection::Mirrored(Vec2::new(200.0, 200.0))),
+                (-50.0, -225.0, Transformation::AddTop, Ejection::Mirrored(Vec2::new(200.0, 200.0))),
+                (-25.0, 75.0, Transformation::RotateCw, Ejection::Mirrored(Vec2::new(200.0, 200.0))),
+            ],
+            caves: vec![
+                Cave {
+                    position: Vec2::new(500.0, -150.0),
+                    squares: vec![SquarePos(0, 0)],
+                },
             ],
-            cave: Cave {
-                position: Vec2::new(500.0, -150.0),
-                squares: vec![SquarePos(0, 0)],
-            },
             background: Some(String::from("backgrounds/level2.png")),
             spawn_point: (-550.0, -100.0),
             button_pos: None,
             text_blocks: vec![
                 TextBlock {
-                    text: "(press R to restart)".to_string(),
-                    position: Vec2::new(400.0, 325.0),
+                    text: "(press R to restart, U to undo, hold shift to rewind, Esc for options)".to_string(),
+                    position: Vec2::new(325.0, 325.0),
                     font_size: 14.0,
                     min_width: None,
                 },
             ],
+            ..default()
         },
         LevelData {
             blocks: vec![
                 // frame
-                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0 },
-                Block { x: -600.0, y: -250.0, w: 1200.0, h: 150.0 },
-                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0 },
-                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0 },
+                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: -250.0, w: 1200.0, h: 150.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
                 // big ground blocks
-                Block { x: -550.0, y: 100.0, w: 450.0, h: 450.0 },
-                Block { x: 150.0, y: 100.0, w: 450.0, h: 450.0 },
+                Block { x: -550.0, y: 100.0, w: 450.0, h: 450.0, kind: BlockKind::Solid },
+                Block { x: 150.0, y: 100.0, w: 450.0, h: 450.0, kind: BlockKind::Solid },
                 // small blocks
-                Block { x: 0.0, y: 50.0, w: 50.0, h: 50.0 },
-                Block { x: 0.0, y: 200.0, w: 50.0, h: 50.0 },
-                Block { x: 200.0, y: 350.0, w: 50.0, h: 50.0 },
+                Block { x: 0.0, y: 50.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 0.0, y: 200.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 200.0, y: 350.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
             ],
             transformers: vec![
-                (175.0, 125.0, Transformation::AddRight, Vec2::new(200.0, 200.0)),
+                (175.0, 125.0, Transformation::AddRight, Ejection::Mirrored(Vec2::new(200.0, 200.0))),
+            ],
+            caves: vec![
+                Cave {
+                    position: Vec2::new(500.0, 200.0),
+                    squares: vec![SquarePos(0, 0)],
+                },
             ],
-            cave: Cave {
-                position: Vec2::new(500.0, 200.0),
-                squares: vec![SquarePos(0, 0)],
-            },
             background: Some(String::from("backgrounds/level3.png")),
             spawn_point: (-550.0, 300.0),
             button_pos: None,
             text_blocks: vec![],
+            ..default()
         },
         LevelData {
             blocks: vec![
                 // frame
-                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0 },
-                Block { x: -600.0, y: -250.0, w: 1200.0, h: 150.0 },
-                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0 },
-                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0 },
+                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: -250.0, w: 1200.0, h: 150.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
                 // platforms
-                Block { x: -250.0, y: -150.0, w: 250.0, h: 50.0 },
-                Block { x: -500.0, y: 0.0, w: 250.0, h: 50.0 },
-                Block { x: -350.0, y: 200.0, w: 250.0, h: 50.0 },
-                Block { x: 300.0, y: 100.0, w: 250.0, h: 50.0 },
+                Block { x: -250.0, y: -150.0, w: 250.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -500.0, y: 0.0, w: 250.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -350.0, y: 200.0, w: 250.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 300.0, y: 100.0, w: 250.0, h: 50.0, kind: BlockKind::Solid },
                 // small blocks
-                Block { x: -250.0, y: -100.0, w: 50.0, h: 50.0 },
-                Block { x: -250.0, y: -100.0, w: 50.0, h: 50.0 },
-                Block { x: -400.0, y: 50.0, w: 50.0, h: 50.0 },
-                Block { x: -450.0, y: 100.0, w: 50.0, h: 50.0 },
-                Block { x: -50.0, y: 100.0, w: 50.0, h: 50.0 },
-                Block { x: 50.0, y: 200.0, w: 50.0, h: 50.0 },
-                Block { x: 150.0, y: 100.0, w: 50.0, h: 50.0 },
-                Block { x: 250.0, y: 200.0, w: 50.0, h: 50.0 },
-                Block { x: 350.0, y: 350.0, w: 50.0, h: 50.0 },
+                Block { x: -250.0, y: -100.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -250.0, y: -100.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -400.0, y: 50.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -450.0, y: 100.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -50.0, y: 100.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 50.0, y: 200.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 150.0, y: 100.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 250.0, y: 200.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 350.0, y: 350.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
             ],
             transformers: vec![
-                (-75.0, -125.0, Transformation::AddRight, Vec2::new(200.0, 200.0)),
-                (-475.0, 25.0, Transformation::RotateCw, Vec2::new(200.0, 200.0)),
-                (275.0, 225.0, Transformation::AddTop, Vec2::new(300.0, -10.0)),
+                (-75.0, -125.0, Transformation::AddRight, Ejection::Mirrored(Vec2::new(200.0, 200.0))),
+                (-475.0, 25.0, Transformation::RotateCw, Ejection::Mirrored(Vec2::new(200.0, 200.0))),
+                (275.0, 225.0, Transformation::AddTop, Ejection::Mirrored(Vec2::new(300.0, -10.0))),
+            ],
+            caves: vec![
+                Cave {
+                    position: Vec2::new(500.0, 200.0),
+                    squares: vec![SquarePos(0, 0)],
+                },
             ],
-            cave: Cave {
-                position: Vec2::new(500.0, 200.0),
-                squares: vec![SquarePos(0, 0)],
-            },
             background: Some(String::from("backgrounds/level4.png")),
             spawn_point: (-550.0, -100.0),
             button_pos: None,
             text_blocks: vec![],
+            ..default()
         },
         LevelData {
             blocks: vec![
                 // frame
-                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0 },
-                Block { x: -600.0, y: -350.0, w: 1200.0, h: 50.0 },
-                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0 },
-                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0 },
+                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: -350.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
                 // big blocks
-                Block { x: -600.0, y: 150.0, w: 475.0, h: 300.0 },
-                Block { x: -50.0, y: 150.0, w: 600.0, h: 300.0 },
+                Block { x: -600.0, y: 150.0, w: 475.0, h: 300.0, kind: BlockKind::Solid },
+                Block { x: -50.0, y: 150.0, w: 600.0, h: 300.0, kind: BlockKind::Solid },
             ],
             transformers: vec![
-                (200.0, 175.0, Transformation::AddRight, Vec2::new(100.0, 200.0)),
-                (500.0, 175.0, Transformation::RotateCw, Vec2::new(-100.0, 200.0)),
+                (200.0, 175.0, Transformation::AddRight, Ejection::Mirrored(Vec2::new(100.0, 200.0))),
+                (500.0, 175.0, Transformation::RotateCw, Ejection::Mirrored(Vec2::new(-100.0, 200.0))),
+            ],
+            caves: vec![
+                Cave {
+                    position: Vec2::new(500.0, -225.0),
+                    squares: vec![SquarePos(0, 0), SquarePos(0, 1)],
+                },
             ],
-            cave: Cave {
-                position: Vec2::new(500.0, -225.0),
-                squares: vec![SquarePos(0, 0), SquarePos(0, 1)],
-            },
             background: Some(String::from("backgrounds/level6.png")),
             spawn_point: (-550.0, 300.0),
             button_pos: None,
             text_blocks: vec![],
+            ..default()
         },
         LevelData {
             blocks: vec![
                 // frame
-                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0 },
-                Block { x: -600.0, y: -250.0, w: 1200.0, h: 150.0 },
-                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0 },
-                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0 },
+                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: -250.0, w: 1200.0, h: 150.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
                 // wall
-                Block { x: 250.0, y: -100.0, w: 50.0, h: 200.0 },
+                Block { x: 250.0, y: -100.0, w: 50.0, h: 200.0, kind: BlockKind::Solid },
                 // small blocks
-                Block { x: -400.0, y: -150.0, w: 50.0, h: 50.0 },
-                Block { x: -250.0, y: -150.0, w: 50.0, h: 50.0 },
-                Block { x: -50.0, y: -50.0, w: 100.0, h: 50.0 },
+                Block { x: -400.0, y: -150.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -250.0, y: -150.0, w: 50.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -50.0, y: -50.0, w: 100.0, h: 50.0, kind: BlockKind::Solid },
             ],
             transformers: vec![
-                (-225.0, -125.0, Transformation::AddRight, Vec2::new(100.0, 400.0)),
-                (25.0, -25.0, Transformation::RotateCw, Vec2::new(-300.0, 300.0)),
+                (-225.0, -125.0, Transformation::AddRight, Ejection::Mirrored(Vec2::new(100.0, 400.0))),
+                (25.0, -25.0, Transformation::RotateCw, Ejection::Mirrored(Vec2::new(-300.0, 300.0))),
+            ],
+            caves: vec![
+                Cave {
+                    position: Vec2::new(475.0, -125.0),
+                    squares: vec![SquarePos(0, 0), SquarePos(0, 1), SquarePos(1, 1)],
+                },
             ],
-            cave: Cave {
-                position: Vec2::new(475.0, -125.0),
-                squares: vec![SquarePos(0, 0), SquarePos(0, 1), SquarePos(1, 1)],
-            },
             background: Some(String::from("backgrounds/level5.png")),
             spawn_point: (-550.0, -100.0),
             button_pos: None,
             text_blocks: vec![],
+            ..default()
         },
         LevelData {
             blocks: vec![
                 // frame
-                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0 },
-                Block { x: -600.0, y: -350.0, w: 1200.0, h: 50.0 },
-                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0 },
-                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0 },
+                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: -350.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
                 // horizontal platforms
-                Block { x: -550.0, y: 150.0, w: 200.0, h: 50.0 },
-                Block { x: -250.0, y: 150.0, w: 300.0, h: 50.0 },
-                Block { x: 125.0, y: 150.0, w: 200.0, h: 50.0 },
-                Block { x: -500.0, y: -100.0, w: 250.0, h: 50.0 },
-                Block { x: -125.0, y: -100.0, w: 325.0, h: 50.0 },
-                Block { x: 240.0, y: -100.0, w: 160.0, h: 50.0 },
+                Block { x: -550.0, y: 150.0, w: 200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -250.0, y: 150.0, w: 300.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 125.0, y: 150.0, w: 200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -500.0, y: -100.0, w: 250.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -125.0, y: -100.0, w: 325.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 240.0, y: -100.0, w: 160.0, h: 50.0, kind: BlockKind::Solid },
                 // big wall
-                Block { x: 400.0, y: 350.0, w: 150.0, h: 500.0 },
+                Block { x: 400.0, y: 350.0, w: 150.0, h: 500.0, kind: BlockKind::Solid },
                 // vertical walls
-                Block { x: -350.0, y: 350.0, w: 50.0, h: 25.0},
-                Block { x: -100.0, y: 350.0, w: 50.0, h: 25.0},
-                Block { x: 150.0, y: 350.0, w: 50.0, h: 275.0},
-                Block { x: -350.0, y: 250.0, w: 50.0, h: 300.0},
-                Block { x: -100.0, y: 250.0, w: 50.0, h: 300.0},
-                Block { x: -350.0, y: -100.0, w: 50.0, h: 250.0},
-                Block { x: -100.0, y: -100.0, w: 50.0, h: 200.0},
-                Block { x: 150.0, y: 0.0, w: 50.0, h: 300.0},
+                Block { x: -350.0, y: 350.0, w: 50.0, h: 25.0, kind: BlockKind::Solid },
+                Block { x: -100.0, y: 350.0, w: 50.0, h: 25.0, kind: BlockKind::Solid },
+                Block { x: 150.0, y: 350.0, w: 50.0, h: 275.0, kind: BlockKind::Solid },
+                Block { x: -350.0, y: 250.0, w: 50.0, h: 300.0, kind: BlockKind::Solid },
+                Block { x: -100.0, y: 250.0, w: 50.0, h: 300.0, kind: BlockKind::Solid },
+                Block { x: -350.0, y: -100.0, w: 50.0, h: 250.0, kind: BlockKind::Solid },
+                Block { x: -100.0, y: -100.0, w: 50.0, h: 200.0, kind: BlockKind::Solid },
+                Block { x: 150.0, y: 0.0, w: 50.0, h: 300.0, kind: BlockKind::Solid },
             ],
             transformers: vec![
-                (-375.0, 175.0, Transformation::AddRight, Vec2::new(-100.0, 200.0)),
-                (-125.0, 175.0, Transformation::RotateCw, Vec2::new(-200.0, 200.0)),
-                (-25.0, 175.0, Transformation::RotateCw, Vec2::new(-200.0, 200.0)),
-                (300.0, 175.0, Transformation::RotateCw, Vec2::new(-100.0, 200.0)),
-                (-425.0, -75.0, Transformation::RotateCw, Vec2::new(200.0, 200.0)),
-                (-25.0, 25.0, Transformation::RotateCw, Vec2::new(-100.0, -100.0)),
-                (300.0, -75.0, Transformation::RotateCw, Vec2::new(-100.0, 200.0)),
-                (300.0, -175.0, Transformation::RotateCw, Vec2::new(0.0, -200.0)),
-                (50.0, -175.0, Transformation::RotateCw, Vec2::new(0.0, -200.0)),
-            ],
-            cave: Cave {
-                position: Vec2::new(500.0, -225.0),
-                squares: vec![SquarePos(0, 0), SquarePos(0, 1)],
-            },
+                (-375.0, 175.0, Transformation::AddRight, Ejection::Mirrored(Vec2::new(-100.0, 200.0))),
+                (-125.0, 175.0, Transformation::RotateCw, Ejection::Mirrored(Vec2::new(-200.0, 200.0))),
+                (-25.0, 175.0, Transformation::RotateCw, Ejection::Mirrored(Vec2::new(-200.0, 200.0))),
+                (300.0, 175.0, Transformation::RotateCw, Ejection::Mirrored(Vec2::new(-100.0, 200.0))),
+                (-425.0, -75.0, Transformation::RotateCw, Ejection::Mirrored(Vec2::new(200.0, 200.0))),
+                (-25.0, 25.0, Transformation::RotateCw, Ejection::Mirrored(Vec2::new(-100.0, -100.0))),
+                (300.0, -75.0, Transformation::RotateCw, Ejection::Mirrored(Vec2::new(-100.0, 200.0))),
+                (300.0, -175.0, Transformation::RotateCw, Ejection::Fixed(Vec2::new(0.0, -200.0))),
+                (50.0, -175.0, Transformation::RotateCw, Ejection::Fixed(Vec2::new(0.0, -200.0))),
+            ],
+            checkpoints: vec![
+                (100.0, -75.0),
+            ],
+            caves: vec![
+                Cave {
+                    position: Vec2::new(500.0, -225.0),
+                    squares: vec![SquarePos(0, 0), SquarePos(0, 1)],
+                },
+            ],
             background: Some(String::from("backgrounds/level7.png")),
             spawn_point: (-550.0, 300.0),
             button_pos: None,
             text_blocks: vec![],
+            ..default()
         },
 
 
 
         LevelData {
             blocks: vec![
-                Block { x: -400.0, y: -200.0, w: 800.0, h: 50.0 },
-                Block { x: 200.0, y: -100.0, w: 50.0, h: 100.0 },
+                Block { x: -400.0, y: -200.0, w: 800.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 200.0, y: -100.0, w: 50.0, h: 100.0, kind: BlockKind::Solid },
             ],
             transformers: vec![
-                (-100.0, -175.0, Transformation::AddRight, Vec2::new(200.0, 200.0)),
+                (-100.0, -175.0, Transformation::AddRight, Ejection::Mirrored(Vec2::new(200.0, 200.0))),
+            ],
+            caves: vec![
+                Cave {
+                    position: Vec2::new(-350.0, -150.0),
+                    squares: vec![SquarePos(0, 0)],
+                },
             ],
-            cave: Cave {
-                position: Vec2::new(-350.0, -150.0),
-                squares: vec![SquarePos(0, 0)],
-            },
             background: None,
             spawn_point: (0.0, 0.0),
             button_pos: None,
@@ -366,41 +483,363 @@ I can't afford to change my shape. I won't be able to fit into my caves anymore.
                     min_width: Some(500.0),
                 },
             ],
+            ..default()
         },
         LevelData {
             blocks: vec![
-                Block { x: -300.0, y: -300.0, w: 600.0, h: 50.0 },
+                Block { x: -300.0, y: -300.0, w: 600.0, h: 50.0, kind: BlockKind::Solid },
             ],
             transformers: vec![
-                (-100.0, -275.0, Transformation::AddRight, Vec2::new(200.0, 200.0)),
+                (-100.0, -275.0, Transformation::AddRight, Ejection::Mirrored(Vec2::new(200.0, 200.0))),
+            ],
+            caves: vec![
+                Cave {
+                    position: Vec2::new(-400.0, -200.0),
+                    squares: vec![SquarePos(0, 0)],
+                },
             ],
-            cave: Cave {
-                position: Vec2::new(-400.0, -200.0),
-                squares: vec![SquarePos(0, 0)],
-            },
             background: None,
             spawn_point: (0.0, 0.0),
             button_pos: None,
             text_blocks: vec![],
+            ..default()
+        },
+    ]
+}
+
+/// Levels for two players at once, picked from the screen after the title.
+fn get_coop_levels() -> Vec<LevelData> {
+    vec![
+        LevelData {
+            blocks: vec![
+                // frame
+                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: -350.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+            ],
+            transformers: vec![
+                (-300.0, -325.0, Transformation::AddRight, Ejection::Mirrored(Vec2::new(100.0, 200.0))),
+            ],
+            // too wide for either player alone
+            caves: vec![
+                Cave {
+                    position: Vec2::new(50.0, -300.0),
+                    squares: vec![SquarePos(0, 0), SquarePos(1, 0), SquarePos(2, 0)],
+                },
+            ],
+            background: None,
+            spawn_point: (-500.0, -300.0),
+            coop_spawn_point: Some((500.0, -300.0)),
+            button_pos: None,
+            text_blocks: vec![
+                TextBlock {
+                    text: "(two players: the second moves with J and L, jumps with I and undoes with O)".to_string(),
+                    position: Vec2::new(-300.0, 200.0),
+                    font_size: 14.0,
+                    min_width: Some(600.0),
+                },
+            ],
+            ..default()
+        },
+        LevelData {
+            blocks: vec![
+                // frame
+                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: -350.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                // ledge
+                Block { x: -600.0, y: -250.0, w: 250.0, h: 100.0, kind: BlockKind::Solid },
+            ],
+            transformers: vec![
+                (-100.0, -325.0, Transformation::AddTop, Ejection::Mirrored(Vec2::new(100.0, 200.0))),
+                (300.0, -325.0, Transformation::AddTop, Ejection::Mirrored(Vec2::new(100.0, 200.0))),
+            ],
+            fuse_pads: vec![
+                (100.0, -325.0),
+            ],
+            caves: vec![
+                Cave {
+                    position: Vec2::new(-450.0, -175.0),
+                    squares: vec![SquarePos(0, 0), SquarePos(1, 0), SquarePos(0, 1), SquarePos(1, 1)],
+                },
+            ],
+            background: None,
+            spawn_point: (-300.0, -300.0),
+            coop_spawn_point: Some((500.0, -300.0)),
+            button_pos: None,
+            text_blocks: vec![
+                TextBlock {
+                    text: "(stand side by side on the purple pad to fuse together)".to_string(),
+                    position: Vec2::new(-300.0, 200.0),
+                    font_size: 14.0,
+                    min_width: Some(600.0),
+                },
+            ],
+            ..default()
+        },
+    ]
+}
+
+/// Levels that show off one thing each, picked from the screen after the title.
+fn get_showcase_levels() -> Vec<LevelData> {
+    vec![
+        LevelData {
+            blocks: vec![
+                // frame
+                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: -350.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+            ],
+            transformers: vec![],
+            loose_squares: vec![
+                (-300.0, -325.0),
+                (-200.0, -325.0),
+                // has to be bumped from below by the middle of a three wide shape
+                (-50.0, -200.0),
+            ],
+            caves: vec![
+                Cave {
+                    position: Vec2::new(375.0, -275.0),
+                    squares: vec![SquarePos(0, 0), SquarePos(1, 0), SquarePos(2, 0), SquarePos(1, 1)],
+                },
+            ],
+            background: None,
+            spawn_point: (-500.0, -300.0),
+            button_pos: None,
+            text_blocks: vec![
+                TextBlock {
+                    text: "(loose squares stick to whichever side bumps into them)".to_string(),
+                    position: Vec2::new(-300.0, 200.0),
+                    font_size: 14.0,
+                    min_width: Some(600.0),
+                },
+            ],
+            ..default()
+        },
+        LevelData {
+            blocks: vec![
+                // frame
+                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: -350.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                // too high to jump onto
+                Block { x: 200.0, y: -150.0, w: 350.0, h: 200.0, kind: BlockKind::Solid },
+            ],
+            transformers: vec![
+                (-300.0, -325.0, Transformation::AddRight, Ejection::ArcTo(Vec2::new(300.0, -125.0))),
+            ],
+            caves: vec![
+                Cave {
+                    position: Vec2::new(500.0, -100.0),
+                    squares: vec![SquarePos(0, 0), SquarePos(1, 0)],
+                },
+            ],
+            background: None,
+            spawn_point: (-500.0, -300.0),
+            button_pos: None,
+            text_blocks: vec![
+                TextBlock {
+                    text: "(some transformers throw you in an arc that lands on a set spot)".to_string(),
+                    position: Vec2::new(-300.0, 200.0),
+                    font_size: 14.0,
+                    min_width: Some(600.0),
+                },
+            ],
+            ..default()
+        },
+        LevelData {
+            blocks: vec![
+                // frame, with a pit in the middle
+                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: -350.0, w: 300.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 300.0, y: -350.0, w: 300.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+            ],
+            moving_blocks: vec![
+                // lift that lowers the player to the floor at the start
+                (
+                    Block { x: -550.0, y: 50.0, w: 100.0, h: 25.0, kind: BlockKind::Solid },
+                    Waypoints { offsets: vec![Vec2::new(0.0, -375.0)], speed: 150.0, mode: PathMode::Linear },
+                ),
+                // ferry across the pit, coming back high up
+                (
+                    Block { x: -300.0, y: -350.0, w: 150.0, h: 25.0, kind: BlockKind::Solid },
+                    Waypoints {
+                        offsets: vec![Vec2::new(450.0, 0.0), Vec2::new(450.0, 150.0), Vec2::new(0.0, 150.0)],
+                        speed: 100.0,
+                        mode: PathMode::Loop,
+                    },
+                ),
+            ],
+            transformers: vec![],
+            moving_transformers: vec![
+                (
+                    350.0, -325.0, Transformation::AddTop, Ejection::Mirrored(Vec2::new(100.0, 200.0)),
+                    Waypoints { offsets: vec![Vec2::new(100.0, 0.0)], speed: 60.0, mode: PathMode::PingPong },
+                ),
+            ],
+            caves: vec![
+                Cave {
+                    position: Vec2::new(550.0, -275.0),
+                    squares: vec![SquarePos(0, 0), SquarePos(0, 1)],
+                },
+            ],
+            background: None,
+            spawn_point: (-500.0, 100.0),
+            button_pos: None,
+            text_blocks: vec![
+                TextBlock {
+                    text: "(platforms and transformers can move too)".to_string(),
+                    position: Vec2::new(-300.0, 200.0),
+                    font_size: 14.0,
+                    min_width: Some(600.0),
+                },
+            ],
+            ..default()
+        },
+        LevelData {
+            blocks: vec![
+                // frame
+                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: -350.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+            ],
+            hazards: vec![
+                // low enough to take the top off a shape three squares tall
+                (Block { x: 0.0, y: -175.0, w: 100.0, h: 50.0, kind: BlockKind::Solid }, Hazard::Shredder),
+                (Block { x: 200.0, y: -325.0, w: 100.0, h: 25.0, kind: BlockKind::Solid }, Hazard::Spikes),
+            ],
+            transformers: vec![
+                (-350.0, -325.0, Transformation::AddTop, Ejection::Mirrored(Vec2::new(100.0, 200.0))),
+                (-150.0, -325.0, Transformation::AddTop, Ejection::Mirrored(Vec2::new(100.0, 200.0))),
+            ],
+            caves: vec![
+                Cave {
+                    position: Vec2::new(550.0, -275.0),
+                    squares: vec![SquarePos(0, 0), SquarePos(0, 1)],
+                },
+            ],
+            background: None,
+            spawn_point: (-500.0, -300.0),
+            button_pos: None,
+            text_blocks: vec![
+                TextBlock {
+                    text: "(spikes hurt, and shredders take off any square that touches them)".to_string(),
+                    position: Vec2::new(-300.0, 200.0),
+                    font_size: 14.0,
+                    min_width: Some(600.0),
+                },
+            ],
+            ..default()
+        },
+        LevelData {
+            blocks: vec![
+                // frame
+                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: -350.0, w: 450.0, h: 50.0, kind: BlockKind::Ice },
+                Block { x: -150.0, y: -350.0, w: 750.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+
+                Block { x: -100.0, y: -325.0, w: 100.0, h: 25.0, kind: BlockKind::Bouncy },
+                // each step is a single jump above the last
+                Block { x: 50.0, y: -250.0, w: 150.0, h: 20.0, kind: BlockKind::OneWay },
+                Block { x: 200.0, y: -150.0, w: 150.0, h: 20.0, kind: BlockKind::OneWay },
+                Block { x: 350.0, y: -50.0, w: 200.0, h: 50.0, kind: BlockKind::Solid },
+            ],
+            transformers: vec![],
+            caves: vec![
+                Cave {
+                    position: Vec2::new(450.0, 0.0),
+                    squares: vec![SquarePos(0, 0)],
+                },
+            ],
+            background: None,
+            spawn_point: (-500.0, -300.0),
+            button_pos: None,
+            text_blocks: vec![
+                TextBlock {
+                    text: "(light blue is slippery, green is bouncy, and you can jump up through thin platforms)".to_string(),
+                    position: Vec2::new(-300.0, 200.0),
+                    font_size: 14.0,
+                    min_width: Some(600.0),
+                },
+            ],
+            ..default()
+        },
+        LevelData {
+            blocks: vec![
+                // frame
+                Block { x: -600.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: -350.0, w: 550.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 150.0, y: -350.0, w: 450.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: -600.0, y: 400.0, w: 1200.0, h: 50.0, kind: BlockKind::Solid },
+                Block { x: 550.0, y: 400.0, w: 50.0, h: 800.0, kind: BlockKind::Solid },
+
+                // too low to jump the pit under
+                Block { x: -75.0, y: -140.0, w: 250.0, h: 150.0, kind: BlockKind::Solid },
+            ],
+            transformers: vec![
+                (-400.0, -325.0, Transformation::AddRight, Ejection::Mirrored(Vec2::new(100.0, 200.0))),
+                (250.0, -325.0, Transformation::AddTop, Ejection::Mirrored(Vec2::new(100.0, 200.0))),
+            ],
+            pressure_plates: vec![
+                (-125.0, -325.0, PlateRequirement::Footprint(vec![SquarePos(0, 0), SquarePos(1, 0)]), 0),
+                (350.0, -325.0, PlateRequirement::MinSquares { squares: 3, width: 1 }, 1),
+            ],
+            gates: vec![
+                (Block { x: -50.0, y: -350.0, w: 200.0, h: 50.0, kind: BlockKind::Solid }, GateKind::Bridge, 0),
+                (Block { x: 400.0, y: 350.0, w: 50.0, h: 700.0, kind: BlockKind::Solid }, GateKind::Door, 1),
+            ],
+            caves: vec![
+                Cave {
+                    position: Vec2::new(525.0, -275.0),
+                    squares: vec![SquarePos(0, 0), SquarePos(1, 0), SquarePos(0, 1)],
+                },
+            ],
+            background: None,
+            spawn_point: (-500.0, -300.0),
+            button_pos: None,
+            text_blocks: vec![
+                TextBlock {
+                    text: "(plates need weighing down: a number says how many squares it takes, and an outlined one only takes that shape)".to_string(),
+                    position: Vec2::new(-300.0, 200.0),
+                    font_size: 14.0,
+                    min_width: Some(600.0),
+                },
+            ],
+            ..default()
         },
     ]
 }
 
+type ContinueButtonFilter = (Changed<Interaction>, With<ContinueButton>);
+
 pub fn button_system(
     mut commands: Commands,
-    mut interaction_query: Query<
-        (&Interaction, &mut BackgroundColor),
-        (Changed<Interaction>, With<Button>),
-    >,
+    mut interaction_query: Query<(&Interaction, &mut BackgroundColor, &ContinueButton), ContinueButtonFilter>,
+    levels: Query<&Level>,
     mut level_transitioning: ResMut<LevelTransitioning>,
 ) {
-    for (interaction, mut color) in &mut interaction_query {
+    for (interaction, mut color, ContinueButton(set)) in &mut interaction_query {
         match *interaction {
             Interaction::Pressed => {
                 *color = BUTTON_COLOR.into();
                 if !level_transitioning.0 {
+                    let level = levels.iter().next().unwrap();
+                    let destination = if *set == level.set {
+                        level_after(level.set, level.levelid)
+                    } else {
+                        (*set, 0)
+                    };
                     level_transitioning.0 = true;
-                    spawn_fade_to_black(&mut commands);
+                    spawn_fade_to_black(&mut commands, destination);
                 }
             }
             Interaction::Hovered => {
@@ -413,10 +852,23 @@ pub fn button_system(
     }
 }
 
-pub fn start_level(commands: &mut Commands, asset_server: &Res<AssetServer>, levelid: usize) {
-    let levels = get_levels();
+pub fn start_level(commands: &mut Commands, asset_server: &Res<AssetServer>, set: LevelSet, levelid: usize) {
+    let levels = set.levels();
     let level_data = &levels[levelid];
-    let level = Level { levelid, spawn_point: level_data.spawn_point };
+    // checkpoints reached on an earlier visit to this level belong to flags that are about to be despawned
+    commands.insert_resource(ActiveCheckpoint::default());
+    let level = Level {
+        set,
+        levelid,
+        spawn_point: level_data.spawn_point,
+        coop_spawn_point: level_data.coop_spawn_point,
+        kill_bounds: level_data.kill_bounds.unwrap_or(Rect::new(
+            -WINDOW_WIDTH / 2.0,
+            -WINDOW_HEIGHT / 2.0,
+            WINDOW_WIDTH / 2.0,
+            WINDOW_HEIGHT / 2.0,
+        )),
+    };
     let font = asset_server.load("fonts/bahnschrift.ttf");
     if let Some(background_path) = &level_data.background {
         commands.spawn((
@@ -438,8 +890,10 @@ pub fn start_level(commands: &mut Commands, asset_server: &Res<AssetServer>, lev
             NodeBundle {
                 style: Style {
                     width: Val::Percent(100.0),
+                    flex_direction: FlexDirection::Column,
                     align_items: AlignItems::Center,
                     justify_content: JustifyContent::Center,
+                    row_gap: Val::Px(16.0),
                     ..default()
                 },
                 transform: Transform::from_xyz(0.0, 0.0, 0.0),
@@ -447,7 +901,8 @@ pub fn start_level(commands: &mut Commands, asset_server: &Res<AssetServer>, lev
             },
             level,
         )).with_children(|parent| {
-                parent.spawn(
+            for set in LevelSet::ALL {
+                parent.spawn((
                     ButtonBundle {
                         style: Style {
                             width: Val::Px(150.0),
@@ -462,30 +917,83 @@ pub fn start_level(commands: &mut Commands, asset_server: &Res<AssetServer>, lev
                         },
                         background_color: BUTTON_COLOR.into(),
                         ..default()
-                    }
-                ).with_children(|parent2| {
+                    },
+                    ContinueButton(set),
+                )).with_children(|parent2| {
                     parent2.spawn(TextBundle::from_section(
-                        "(continue)",
-                        text_style,
+                        set.button_label(),
+                        text_style.clone(),
                     ));
                 });
             }
-        );
+        });
     }
     for block in &level_data.blocks {
         commands.spawn((
             block.to_sprite_bundle(),
             block.to_collider(),
+            block.kind.surface(),
+            block.kind,
             level,
         ));
     }
+    for (block, waypoints) in &level_data.moving_blocks {
+        commands.spawn((
+            block.to_sprite_bundle(),
+            block.to_collider(),
+            block.kind.surface(),
+            block.kind,
+            RigidBody::KinematicPositionBased,
+            FollowPath::new(Vec2::new(block.x_ctr(), block.y_ctr()), waypoints.clone()),
+            level,
+        ));
+    }
+    for (block, hazard) in &level_data.hazards {
+        let center = Vec2::new(block.x_ctr(), block.y_ctr());
+        let entity = spawn_hazard(commands, *hazard, center, Vec2::new(block.w, block.h));
+        commands.entity(entity).insert(level);
+    }
     for transformer_args in &level_data.transformers {
-        let (x, y, transformation, spit_direction) = transformer_args;
+        let (x, y, transformation, ejection) = transformer_args;
+        commands.spawn((
+            TransformerBundle::new(*x, *y, *transformation, *ejection, asset_server),
+            level,
+        ));
+    }
+    for (x, y, transformation, ejection, waypoints) in &level_data.moving_transformers {
+        commands.spawn((
+            TransformerBundle::new(*x, *y, *transformation, *ejection, asset_server),
+            FollowPath::new(Vec2::new(*x, *y), waypoints.clone()),
+            level,
+        ));
+    }
+    for (x, y) in &level_data.checkpoints {
+        commands.spawn((
+            CheckpointBundle::new(*x, *y),
+            level,
+        ));
+    }
+    for (x, y) in &level_data.fuse_pads {
+        commands.spawn((
+            FusePadBundle::new(*x, *y),
+            level,
+        ));
+    }
+    for (x, y) in &level_data.loose_squares {
         commands.spawn((
-            TransformerBundle::new(*x, *y, *transformation, *spit_direction, asset_server),
+            LooseSquareBundle::new(*x, *y),
             level,
         ));
     }
+    for (x, y, requirement, link) in &level_data.pressure_plates {
+        let entity = spawn_pressure_plate(commands, *x, *y, requirement.clone(), *link, font.clone());
+        commands.entity(entity).insert(level);
+    }
+    for (block, kind, link) in &level_data.gates {
+        let center = Vec2::new(block.x_ctr(), block.y_ctr());
+        let entity = spawn_gate(commands, *kind, *link, center, Vec2::new(block.w, block.h));
+        commands.entity(entity).insert(level);
+    }
     for text_block in &level_data.text_blocks {
         let text_style = TextStyle {
             font: font.clone(),
@@ -509,44 +1017,49 @@ pub fn start_level(commands: &mut Commands, asset_server: &Res<AssetServer>, lev
             level,
         ));
     }
-    commands.spawn((
-        CaveBundle::new(level_data.cave.clone()),
-        level,
-    ));
+    for cave in &level_data.caves {
+        commands.spawn((
+            CaveBundle::new(cave.clone()),
+            level,
+        ));
+    }
 }
 
 pub fn spawn_map(mut commands: Commands, asset_server: Res<AssetServer>) {
-    start_level(&mut commands, &asset_server, 0);
+    start_level(&mut commands, &asset_server, LevelSet::Story, 0);
 }
 
 pub fn next_level(
     mut commands: Commands,
     mut player_info: Query<(Entity, &Player, &mut Transform, &mut Velocity)>,
     caves: Query<&Cave>,
+    levels: Query<&Level>,
     mut level_transitioning: ResMut<LevelTransitioning>,
 ) {
-    if level_transitioning.0 {
+    if level_transitioning.0 || caves.is_empty() {
         return;
     }
-    let mut new_level = false;
-    for (player_entity, player, mut player_transform, mut velocity) in &mut player_info {
-        for cave in &caves {
-            let (w, h) = cave.get_dimens();
-            let cave_x = cave.position.x - w as f32 * PLAYER_WIDTH / 2.0;
-            let cave_y = cave.position.y - h as f32 * PLAYER_HEIGHT / 2.0;
-            if (player_transform.translation.x - cave_x).abs() < PLAYER_WIDTH / 2.0
-                    && (player_transform.translation.y - cave_y).abs() < PLAYER_HEIGHT / 2.0
-                    && cave.matches_player(&player) {
-                // new level
-                player_transform.translation = Vec3::new(cave_x, cave_y, 0.0);
-                velocity.linvel = Vec2::new(0.0, 0.0);
-                commands.entity(player_entity).remove::<RigidBody>();
-                new_level = true;
-            }
+    // every cave has to be filled, by one player each or by both together
+    let mut filling = vec![];
+    for cave in &caves {
+        let players = player_info.iter().map(|(entity, player, transform, _)| {
+            (entity, player, transform.translation.truncate())
+        });
+        match cave.filled_by(players) {
+            Some(players) => filling.extend(players.into_iter().map(|(entity, offset)| (entity, cave, offset))),
+            None => return,
         }
     }
-    if new_level {
-        level_transitioning.0 = true;
-        spawn_fade_to_black(&mut commands);
+
+    // new level
+    for (player_entity, cave, offset) in filling {
+        let Ok((_, _, mut player_transform, mut velocity)) = player_info.get_mut(player_entity) else { continue };
+        let position = cave.origin() + offset.as_vec2() * Vec2::new(PLAYER_WIDTH, PLAYER_HEIGHT);
+        player_transform.translation = position.extend(0.0);
+        velocity.linvel = Vec2::new(0.0, 0.0);
+        commands.entity(player_entity).remove::<RigidBody>();
     }
+    let level = levels.iter().next().unwrap();
+    level_transitioning.0 = true;
+    spawn_fade_to_black(&mut commands, level_after(level.set, level.levelid));
 }
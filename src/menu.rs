@@ -0,0 +1,336 @@
+use bevy::{prelude::*, ecs::system::SystemParam};
+use bevy_rapier2d::prelude::*;
+use crate::input::{Action, ActionState, InputBindings};
+use crate::map::{ContinueButton, BUTTON_COLOR, BUTTON_COLOR_HOVER};
+
+const MENU_BACKGROUND_COLOR: Color = Color::Rgba {
+    red: 0.14453125,
+    green: 0.125,
+    blue: 0.19140625,
+    alpha: 0.9,
+};
+
+const FONT_SIZE: f32 = 18.0;
+
+#[derive(Resource)]
+pub struct Paused(pub bool);
+
+/// The player and action waiting for a new key, if one is being rebound.
+#[derive(Resource, Default)]
+pub struct Rebinding(pub Option<(usize, Action)>);
+
+#[derive(Component)]
+pub struct PauseMenu;
+
+#[derive(Component)]
+pub struct RebindButton(usize, Action);
+
+#[derive(Component)]
+pub struct BindingText(usize, Action);
+
+#[derive(Component)]
+pub struct ResumeButton;
+
+/// Run condition for everything that should stop while the game is paused.
+pub fn game_running(paused: Res<Paused>) -> bool {
+    !paused.0
+}
+
+fn menu_button(width: f32) -> ButtonBundle {
+    ButtonBundle {
+        style: Style {
+            width: Val::Px(width),
+            height: Val::Px(32.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            ..default()
+        },
+        background_color: BUTTON_COLOR.into(),
+        ..default()
+    }
+}
+
+fn spawn_pause_menu(commands: &mut Commands, asset_server: &AssetServer, bindings: &InputBindings) {
+    let text_style = TextStyle {
+        font: asset_server.load("fonts/bahnschrift.ttf"),
+        font_size: FONT_SIZE,
+        color: Color::WHITE,
+    };
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                flex_direction: FlexDirection::Column,
+                align_items: AlignItems::Center,
+                justify_content: JustifyContent::Center,
+                row_gap: Val::Px(6.0),
+                ..default()
+            },
+            background_color: MENU_BACKGROUND_COLOR.into(),
+            z_index: ZIndex::Global(10),
+            ..default()
+        },
+        PauseMenu,
+    )).with_children(|menu| {
+        menu.spawn(TextBundle::from_section("paused", TextStyle {
+            font_size: FONT_SIZE * 2.0,
+            ..text_style.clone()
+        }));
+        let rows = Action::ALL.into_iter().map(|action| (0, action))
+            .chain(Action::PER_PLAYER.into_iter().map(|action| (1, action)));
+        for (player, action) in rows {
+            let name = match player {
+                0 => action.get_name().to_string(),
+                _ => format!("p{} {}", player + 1, action.get_name()),
+            };
+            menu.spawn(NodeBundle {
+                style: Style {
+                    align_items: AlignItems::Center,
+                    column_gap: Val::Px(12.0),
+                    ..default()
+                },
+                ..default()
+            }).with_children(|row| {
+                row.spawn(TextBundle::from_section(name, text_style.clone()).with_style(Style {
+                    width: Val::Px(150.0),
+                    ..default()
+                }));
+                row.spawn((menu_button(240.0), RebindButton(player, action))).with_children(|button| {
+                    button.spawn((
+                        TextBundle::from_section(bindings.describe(player, action), text_style.clone()),
+                        BindingText(player, action),
+                    ));
+                });
+            });
+        }
+        menu.spawn((menu_button(150.0), ResumeButton)).with_children(|button| {
+            button.spawn(TextBundle::from_section("(resume)", text_style.clone()));
+        });
+    });
+}
+
+/// Everything needed to pause or unpause the game and open or close the menu along with it.
+#[derive(SystemParam)]
+pub struct PauseControl<'w, 's> {
+    commands: Commands<'w, 's>,
+    paused: ResMut<'w, Paused>,
+    rapier_config: ResMut<'w, RapierConfiguration>,
+    menus: Query<'w, 's, Entity, With<PauseMenu>>,
+    asset_server: Res<'w, AssetServer>,
+    bindings: Res<'w, InputBindings>,
+}
+
+impl PauseControl<'_, '_> {
+    fn set_paused(&mut self, value: bool) {
+        self.paused.0 = value;
+        self.rapier_config.physics_pipeline_active = !value;
+        for entity in &self.menus {
+            self.commands.entity(entity).despawn_recursive();
+        }
+        if value {
+            spawn_pause_menu(&mut self.commands, &self.asset_server, &self.bindings);
+        }
+    }
+}
+
+pub fn toggle_pause(
+    action_state: Res<ActionState>,
+    rebinding: Res<Rebinding>,
+    mut pause_control: PauseControl,
+) {
+    // the pause key cancels a rebind instead of closing the menu
+    if rebinding.0.is_some() || !action_state.just_pressed(Action::Pause) {
+        return;
+    }
+    let value = !pause_control.paused.0;
+    pause_control.set_paused(value);
+}
+
+type MenuButtonFilter = (Changed<Interaction>, Or<(With<RebindButton>, With<ResumeButton>)>);
+
+pub fn pause_menu_buttons(
+    mut interaction_query: Query<(&Interaction, &mut BackgroundColor, Option<&RebindButton>), MenuButtonFilter>,
+    mut rebinding: ResMut<Rebinding>,
+    mut pause_control: PauseControl,
+) {
+    for (interaction, mut color, rebind_button) in &mut interaction_query {
+        match *interaction {
+            Interaction::Pressed => {
+                *color = BUTTON_COLOR.into();
+                if let Some(RebindButton(player, action)) = rebind_button {
+                    rebinding.0 = Some((*player, *action));
+                } else {
+                    rebinding.0 = None;
+                    pause_control.set_paused(false);
+                }
+            }
+            Interaction::Hovered => {
+                *color = BUTTON_COLOR_HOVER.into();
+            }
+            Interaction::None => {
+                *color = BUTTON_COLOR.into();
+            }
+        }
+    }
+}
+
+/// Binds the next key pressed to the action being rebound. Escape cancels.
+pub fn capture_rebind(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut rebinding: ResMut<Rebinding>,
+    mut bindings: ResMut<InputBindings>,
+) {
+    let Some((player, action)) = rebinding.0 else { return };
+    let Some(&key) = keyboard_input.get_just_pressed().next() else { return };
+    if key != KeyCode::Escape {
+        bindings.rebind(player, action, key);
+        bindings.save();
+    }
+    rebinding.0 = None;
+}
+
+pub fn update_binding_texts(
+    bindings: Res<InputBindings>,
+    rebinding: Res<Rebinding>,
+    mut texts: Query<(&mut Text, &BindingText)>,
+) {
+    if !bindings.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+    for (mut text, &BindingText(player, action)) in &mut texts {
+        text.sections[0].value = if rebinding.0 == Some((player, action)) {
+            "(press a key)".to_string()
+        } else {
+            bindings.describe(player, action)
+        };
+    }
+}
+
+/// The button picked out with the D-pad, for players without a mouse.
+#[derive(Resource, Default)]
+pub struct GamepadFocus(pub Option<Entity>);
+
+/// Moves the focus between the buttons on screen with the D-pad and presses the focused one with South.
+/// Runs after bevy's own ui focus system so that the mouse doesn't immediately undo it.
+pub fn navigate_buttons_with_gamepad(
+    gamepads: Res<Gamepads>,
+    gamepad_input: Res<Input<GamepadButton>>,
+    paused: Res<Paused>,
+    mut focus: ResMut<GamepadFocus>,
+    mut buttons: Query<(Entity, &mut Interaction, &GlobalTransform, Option<&ContinueButton>), With<Button>>,
+) {
+    let just_pressed = |button_type| {
+        gamepads.iter().any(|gamepad| gamepad_input.just_pressed(GamepadButton::new(gamepad, button_type)))
+    };
+
+    // while paused only the menu's buttons can be reached
+    let mut candidates: Vec<(Entity, Vec3)> = buttons.iter()
+        .filter(|(_, _, _, continue_button)| continue_button.is_some() != paused.0)
+        .map(|(entity, _, transform, _)| (entity, transform.translation()))
+        .collect();
+    // ui coordinates grow downwards, so this is top to bottom, then left to right
+    candidates.sort_by(|a, b| a.1.y.total_cmp(&b.1.y).then(a.1.x.total_cmp(&b.1.x)));
+
+    let current = focus.0.and_then(|entity| candidates.iter().position(|&(other, _)| other == entity));
+    if current.is_none() {
+        focus.0 = None;
+    }
+    if candidates.is_empty() {
+        return;
+    }
+    let step = if just_pressed(GamepadButtonType::DPadDown) {
+        1
+    } else if just_pressed(GamepadButtonType::DPadUp) {
+        candidates.len() - 1
+    } else {
+        0
+    };
+    if step != 0 {
+        let index = current.map_or(0, |index| (index + step) % candidates.len());
+        focus.0 = Some(candidates[index].0);
+    }
+
+    let Some(focused) = focus.0 else { return };
+    let Ok((_, mut interaction, _, _)) = buttons.get_mut(focused) else { return };
+    if just_pressed(GamepadButtonType::South) {
+        *interaction = Interaction::Pressed;
+    } else if *interaction != Interaction::Hovered {
+        *interaction = Interaction::Hovered;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::input::test_gamepads::{gamepad_input_app, connect_gamepad};
+    use crate::map::LevelSet;
+    use super::*;
+
+    fn navigation_app(paused: bool) -> (App, Gamepad) {
+        let mut app = gamepad_input_app();
+        app.init_resource::<GamepadFocus>()
+            .insert_resource(Paused(paused))
+            .add_systems(Update, navigate_buttons_with_gamepad);
+        let gamepad = connect_gamepad(&mut app, 0);
+        (app, gamepad)
+    }
+
+    fn spawn_button(app: &mut App, y: f32) -> Entity {
+        app.world.spawn((Button, Interaction::None, GlobalTransform::from_xyz(0.0, y, 0.0))).id()
+    }
+
+    /// Presses and lets go of a button over one frame.
+    fn tap(app: &mut App, gamepad: Gamepad, button_type: GamepadButtonType) {
+        let button = GamepadButton::new(gamepad, button_type);
+        app.world.resource_mut::<Input<GamepadButton>>().press(button);
+        app.update();
+        let mut input = app.world.resource_mut::<Input<GamepadButton>>();
+        input.release(button);
+        input.clear();
+    }
+
+    fn focused(app: &App) -> Option<Entity> {
+        app.world.resource::<GamepadFocus>().0
+    }
+
+    #[test]
+    fn dpad_moves_focus_through_menu_buttons() {
+        let (mut app, gamepad) = navigation_app(true);
+        // ui coordinates grow downwards, so the smaller y is the top button
+        let bottom = spawn_button(&mut app, 200.0);
+        let top = spawn_button(&mut app, 100.0);
+        let continue_button = spawn_button(&mut app, 50.0);
+        app.world.entity_mut(continue_button).insert(ContinueButton(LevelSet::Story));
+
+        app.update();
+        assert_eq!(focused(&app), None);
+
+        tap(&mut app, gamepad, GamepadButtonType::DPadDown);
+        assert_eq!(focused(&app), Some(top));
+        assert_eq!(*app.world.get::<Interaction>(top).unwrap(), Interaction::Hovered);
+
+        tap(&mut app, gamepad, GamepadButtonType::DPadDown);
+        assert_eq!(focused(&app), Some(bottom));
+
+        // wraps around, and never lands on the continue button behind the menu
+        tap(&mut app, gamepad, GamepadButtonType::DPadDown);
+        assert_eq!(focused(&app), Some(top));
+        tap(&mut app, gamepad, GamepadButtonType::DPadUp);
+        assert_eq!(focused(&app), Some(bottom));
+    }
+
+    #[test]
+    fn south_presses_the_focused_button() {
+        let (mut app, gamepad) = navigation_app(false);
+        let menu_button = spawn_button(&mut app, 100.0);
+        let continue_button = spawn_button(&mut app, 200.0);
+        app.world.entity_mut(continue_button).insert(ContinueButton(LevelSet::Story));
+
+        tap(&mut app, gamepad, GamepadButtonType::DPadDown);
+        assert_eq!(focused(&app), Some(continue_button));
+
+        tap(&mut app, gamepad, GamepadButtonType::South);
+        assert_eq!(*app.world.get::<Interaction>(continue_button).unwrap(), Interaction::Pressed);
+        assert_eq!(*app.world.get::<Interaction>(menu_button).unwrap(), Interaction::None);
+    }
+}
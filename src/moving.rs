@@ -0,0 +1,129 @@
+use bevy::prelude::*;
+use crate::player::{Player, Grounded};
+use crate::transformer::Transformer;
+
+#[derive(Copy, Clone)]
+pub enum PathMode {
+    /// Goes through the waypoints once and stops at the last one.
+    Linear,
+    /// Heads straight back to the start after the last waypoint.
+    Loop,
+    /// Turns around at either end.
+    PingPong,
+}
+
+/// A route for a moving platform or transformer, as offsets from where it starts.
+/// The starting position is always the first point.
+#[derive(Clone)]
+pub struct Waypoints {
+    pub offsets: Vec<Vec2>,
+    pub speed: f32,
+    pub mode: PathMode,
+}
+
+#[derive(Component)]
+pub struct FollowPath {
+    origin: Vec2,
+    waypoints: Waypoints,
+    target: usize,
+    forward: bool,
+    /// How far it moved this frame, for carrying whatever is standing on it.
+    pub displacement: Vec2,
+}
+
+impl FollowPath {
+    pub fn new(origin: Vec2, waypoints: Waypoints) -> Self {
+        FollowPath {
+            origin,
+            waypoints,
+            target: 1,
+            forward: true,
+            displacement: Vec2::ZERO,
+        }
+    }
+
+    fn point_count(&self) -> usize {
+        self.waypoints.offsets.len() + 1
+    }
+
+    fn point(&self, index: usize) -> Vec2 {
+        match index {
+            0 => self.origin,
+            _ => self.origin + self.waypoints.offsets[index - 1],
+        }
+    }
+
+    /// Picks the next waypoint to head for, or returns false if there isn't one.
+    fn advance(&mut self) -> bool {
+        let n = self.point_count();
+        match self.waypoints.mode {
+            PathMode::Linear => {
+                if self.target + 1 >= n {
+                    return false;
+                }
+                self.target += 1;
+            },
+            PathMode::Loop => {
+                self.target = (self.target + 1) % n;
+            },
+            PathMode::PingPong => {
+                if self.forward && self.target + 1 >= n {
+                    self.forward = false;
+                } else if !self.forward && self.target == 0 {
+                    self.forward = true;
+                }
+                self.target = if self.forward { self.target + 1 } else { self.target - 1 };
+            },
+        }
+        true
+    }
+
+    /// Where to be after travelling along the path from the given position for this long.
+    fn step(&mut self, position: Vec2, delta_seconds: f32) -> Vec2 {
+        if self.point_count() < 2 {
+            return position;
+        }
+        let mut position = position;
+        let mut remaining = self.waypoints.speed * delta_seconds;
+        // bounded so that a path with every point in the same place can't spin forever
+        for _ in 0..=self.point_count() {
+            let to_target = self.point(self.target) - position;
+            let distance = to_target.length();
+            if distance > remaining {
+                return position + to_target / distance * remaining;
+            }
+            position = self.point(self.target);
+            remaining -= distance;
+            if !self.advance() {
+                break;
+            }
+        }
+        position
+    }
+}
+
+pub fn move_along_paths(
+    mut movers: Query<(&mut FollowPath, &mut Transform, Option<&mut Transformer>)>,
+    time: Res<Time>,
+) {
+    for (mut follow_path, mut transform, transformer) in &mut movers {
+        let position = transform.translation.truncate();
+        let new_position = follow_path.step(position, time.delta_seconds());
+        follow_path.displacement = new_position - position;
+        transform.translation = new_position.extend(transform.translation.z);
+        if let Some(mut transformer) = transformer {
+            transformer.position = new_position;
+        }
+    }
+}
+
+/// Moves players along with the platform they're standing on.
+pub fn carry_players(
+    mut players: Query<(&Grounded, &mut Transform), With<Player>>,
+    platforms: Query<&FollowPath>,
+) {
+    for (grounded, mut transform) in &mut players {
+        let Some(platform) = grounded.0.and_then(|ground| platforms.get(ground).ok()) else { continue };
+        transform.translation += platform.displacement.extend(0.0);
+    }
+}
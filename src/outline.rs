@@ -0,0 +1,135 @@
+use std::collections::{HashMap, HashSet};
+use bevy::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use crate::player::{PLAYER_WIDTH, PLAYER_HEIGHT, SquarePos};
+
+#[derive(Copy, Clone)]
+pub struct OutlineStyle {
+    pub corner_radius: f32,
+    pub grid_lines: bool,
+}
+
+impl OutlineStyle {
+    pub const PLAYER: OutlineStyle = OutlineStyle { corner_radius: 8.0, grid_lines: true };
+    pub const CAVE: OutlineStyle = OutlineStyle { corner_radius: 4.0, grid_lines: false };
+}
+
+// corners are in grid units: corner (x, y) is the bottom left corner of square (x, y)
+type Corner = (i32, i32);
+
+fn corner_to_point(corner: Corner) -> Vec2 {
+    Vec2::new(
+        PLAYER_WIDTH * (corner.0 as f32 - 0.5),
+        PLAYER_HEIGHT * (corner.1 as f32 - 0.5),
+    )
+}
+
+/// Walks the edges that aren't shared between two squares and joins them into closed loops.
+/// Outer boundaries come out counterclockwise and holes clockwise.
+fn boundary_loops(squares: &[SquarePos]) -> Vec<Vec<Corner>> {
+    let cells: HashSet<Corner> = squares.iter().map(|square| (square.0, square.1)).collect();
+
+    let mut edges: HashMap<Corner, Vec<Corner>> = HashMap::new();
+    for &(x, y) in &cells {
+        let sides = [
+            ((x, y), (x + 1, y), (x, y - 1)),
+            ((x + 1, y), (x + 1, y + 1), (x + 1, y)),
+            ((x + 1, y + 1), (x, y + 1), (x, y + 1)),
+            ((x, y + 1), (x, y), (x - 1, y)),
+        ];
+        for (from, to, neighbour) in sides {
+            if !cells.contains(&neighbour) {
+                edges.entry(from).or_default().push(to);
+            }
+        }
+    }
+
+    let mut loops = vec![];
+    let mut starts: Vec<Corner> = edges.keys().copied().collect();
+    starts.sort();
+    for start in starts {
+        while let Some(first) = edges.get_mut(&start).and_then(|ends| ends.pop()) {
+            let mut corners = vec![start];
+            let mut prev = start;
+            let mut cur = first;
+            while cur != start {
+                corners.push(cur);
+                let ends = edges.get_mut(&cur).unwrap();
+                // two squares touching diagonally share a corner with two ways out;
+                // take the left turn so each square keeps its own loop
+                let incoming = (cur.0 - prev.0, cur.1 - prev.1);
+                let index = ends.iter()
+                    .position(|end| {
+                        let outgoing = (end.0 - cur.0, end.1 - cur.1);
+                        incoming.0 * outgoing.1 - incoming.1 * outgoing.0 > 0
+                    })
+                    .unwrap_or(0);
+                prev = cur;
+                cur = ends.swap_remove(index);
+            }
+            loops.push(remove_collinear(corners));
+        }
+    }
+    loops
+}
+
+fn remove_collinear(corners: Vec<Corner>) -> Vec<Corner> {
+    let n = corners.len();
+    (0..n)
+        .filter(|&i| {
+            let prev = corners[(i + n - 1) % n];
+            let cur = corners[i];
+            let next = corners[(i + 1) % n];
+            (cur.0 - prev.0) * (next.1 - cur.1) != (cur.1 - prev.1) * (next.0 - cur.0)
+        })
+        .map(|i| corners[i])
+        .collect()
+}
+
+fn add_loop(builder: &mut PathBuilder, corners: &[Corner], corner_radius: f32) {
+    let points: Vec<Vec2> = corners.iter().map(|&corner| corner_to_point(corner)).collect();
+    let n = points.len();
+    if corner_radius <= 0.0 {
+        builder.move_to(points[0]);
+        for point in &points[1..] {
+            builder.line_to(*point);
+        }
+        builder.close();
+        return;
+    }
+
+    builder.move_to(points[0] + (points[1] - points[0]).normalize() * corner_radius);
+    for i in 1..=n {
+        let prev = points[i - 1];
+        let cur = points[i % n];
+        let next = points[(i + 1) % n];
+        builder.line_to(cur + (prev - cur).normalize() * corner_radius);
+        builder.quadratic_bezier_to(cur, cur + (next - cur).normalize() * corner_radius);
+    }
+    builder.close();
+}
+
+/// Builds a single path tracing the outside of a polyomino, so that it can be both filled and stroked.
+pub fn outline_path(squares: &[SquarePos], style: OutlineStyle) -> Path {
+    let mut builder = PathBuilder::new();
+    // every edge is at least one square long, so this keeps neighbouring corners from overlapping
+    let corner_radius = style.corner_radius.min(PLAYER_WIDTH.min(PLAYER_HEIGHT) / 2.0);
+    for corners in boundary_loops(squares) {
+        add_loop(&mut builder, &corners, corner_radius);
+    }
+
+    if style.grid_lines {
+        let cells: HashSet<Corner> = squares.iter().map(|square| (square.0, square.1)).collect();
+        for &(x, y) in &cells {
+            if cells.contains(&(x + 1, y)) {
+                builder.move_to(corner_to_point((x + 1, y)));
+                builder.line_to(corner_to_point((x + 1, y + 1)));
+            }
+            if cells.contains(&(x, y + 1)) {
+                builder.move_to(corner_to_point((x, y + 1)));
+                builder.line_to(corner_to_point((x + 1, y + 1)));
+            }
+        }
+    }
+    builder.build()
+}
@@ -0,0 +1,121 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use crate::player::{
+    Player, SquarePos, ShapeChange, PLAYER_WIDTH, PLAYER_HEIGHT, PLAYER_OUTLINE_WIDTH, PLAYER_COLOR, PLAYER_OUTLINE_COLOR,
+    normalize_squares,
+};
+use crate::outline::{outline_path, OutlineStyle};
+use crate::transformer::TransformerAnimState;
+use crate::LevelTransitioning;
+
+// loose squares are drawn like faded bits of the player
+const LOOSE_SQUARE_ALPHA: f32 = 0.5;
+const LOOSE_SQUARE_OUTLINE_ALPHA: f32 = 0.8;
+
+// how close the player has to be to count as bumping into the square
+const CONTACT_DISTANCE: f32 = 2.0;
+
+/// A single square lying around the level that sticks to whichever face of the player bumps into it.
+#[derive(Component)]
+pub struct LooseSquare {
+    position: Vec2,
+    collected: bool,
+}
+
+#[derive(Bundle)]
+pub struct LooseSquareBundle {
+    shape_bundle: ShapeBundle,
+    fill: Fill,
+    stroke: Stroke,
+    rigid_body: RigidBody,
+    collider: Collider,
+    loose_square: LooseSquare,
+}
+
+impl LooseSquareBundle {
+    pub fn new(x: f32, y: f32) -> Self {
+        LooseSquareBundle {
+            shape_bundle: ShapeBundle {
+                path: outline_path(&[SquarePos(0, 0)], OutlineStyle::PLAYER),
+                transform: Transform::from_xyz(x, y, 0.0),
+                ..default()
+            },
+            fill: Fill::color(PLAYER_COLOR.with_a(LOOSE_SQUARE_ALPHA)),
+            stroke: Stroke::new(PLAYER_OUTLINE_COLOR.with_a(LOOSE_SQUARE_OUTLINE_ALPHA), PLAYER_OUTLINE_WIDTH),
+            rigid_body: RigidBody::Fixed,
+            collider: Collider::cuboid(PLAYER_WIDTH / 2.0, PLAYER_HEIGHT / 2.0),
+            loose_square: LooseSquare {
+                position: Vec2::new(x, y),
+                collected: false,
+            },
+        }
+    }
+}
+
+/// Brings back every square that's been picked up but isn't part of anyone's shape any more, like after
+/// dying, undoing, rewinding or restarting.
+pub fn return_loose_squares(
+    mut commands: Commands,
+    players: Query<&Player>,
+    mut loose_squares: Query<(Entity, &mut LooseSquare, &mut Visibility)>,
+) {
+    for (entity, mut loose_square, mut visibility) in &mut loose_squares {
+        if loose_square.collected && !players.iter().any(|player| player.carried.contains(&entity)) {
+            loose_square.collected = false;
+            *visibility = Visibility::Inherited;
+            commands.entity(entity).remove::<ColliderDisabled>();
+        }
+    }
+}
+
+pub fn attach_loose_squares(
+    mut commands: Commands,
+    mut players: Query<(&mut Player, &mut Collider, &mut Path, &mut Transform)>,
+    mut loose_squares: Query<(Entity, &mut LooseSquare, &mut Visibility), Without<Player>>,
+    level_transitioning: Res<LevelTransitioning>,
+) {
+    if level_transitioning.0 {
+        return;
+    }
+    for (mut player, mut collider, mut path, mut transform) in &mut players {
+        let TransformerAnimState::NotAnimating = player.transformer_anim_state else { continue };
+        for (entity, mut loose_square, mut visibility) in &mut loose_squares {
+            if loose_square.collected {
+                continue;
+            }
+            let distance = collider.distance_to_point(
+                transform.translation.truncate(),
+                0.0,
+                loose_square.position,
+                true,
+            );
+            if distance > PLAYER_WIDTH.min(PLAYER_HEIGHT) / 2.0 + CONTACT_DISTANCE {
+                continue;
+            }
+            // snap into the nearest cell of the player's grid, which has to be next to one of their squares
+            let cell = ((loose_square.position - transform.translation.truncate())
+                / Vec2::new(PLAYER_WIDTH, PLAYER_HEIGHT)).round().as_ivec2();
+            let cell = SquarePos(cell.x, cell.y);
+            let touching = player.squares.iter().any(|square| (square.0 - cell.0).abs() + (square.1 - cell.1).abs() == 1);
+            if player.squares.contains(&cell) || !touching {
+                continue;
+            }
+
+            player.push_undo(ShapeChange::Pickup);
+            player.carried.push(entity);
+            let mut squares = player.squares.clone();
+            squares.push(cell);
+            let shift = normalize_squares(&mut squares);
+            player.squares = squares;
+            transform.translation += (shift.as_vec2() * Vec2::new(PLAYER_WIDTH, PLAYER_HEIGHT)).extend(0.0);
+            *collider = player.get_collider();
+            *path = player.get_shape();
+
+            // hidden rather than despawned so that it can come back if the shape is reverted
+            loose_square.collected = true;
+            *visibility = Visibility::Hidden;
+            commands.entity(entity).insert(ColliderDisabled);
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use crate::player::{Player, Grounded, SquarePos, PLAYER_WIDTH, PLAYER_HEIGHT, get_dimens, normalize_squares};
+use crate::outline::{outline_path, OutlineStyle};
+use crate::transformer::TransformerAnimState;
+
+const PLATE_HEIGHT: f32 = 8.0;
+// gap between the segments of a plate that only takes one shape
+const SEGMENT_GAP: f32 = 6.0;
+const FOOTPRINT_OUTLINE_ALPHA: f32 = 0.4;
+const FOOTPRINT_OUTLINE_WIDTH: f32 = 2.0;
+// how far above or below the plate, in squares, a square can be and still count as resting on it
+const PLATE_TOLERANCE: f32 = 0.2;
+// how long a gate stays open after its plates are let go, in seconds, so there's time to get through
+const GATE_CLOSE_DELAY: f32 = 1.5;
+const INACTIVE_GATE_ALPHA: f32 = 0.2;
+
+const PLATE_COLOR: Color = Color::Rgba {
+    red: 0.6640625,
+    green: 0.4140625,
+    blue: 0.15234375,
+    alpha: 1.0,
+};
+
+const PLATE_PRESSED_COLOR: Color = Color::Rgba {
+    red: 0.94921875,
+    green: 0.58984375,
+    blue: 0.2109375,
+    alpha: 1.0,
+};
+
+const GATE_COLOR: Color = Color::Rgba {
+    red: 0.94921875,
+    green: 0.58984375,
+    blue: 0.2109375,
+    alpha: 1.0,
+};
+
+#[derive(Clone)]
+pub enum PlateRequirement {
+    /// Pressed by a player with at least this many squares resting on a plate this many squares wide.
+    MinSquares { squares: usize, width: u32 },
+    /// Only pressed by a player in exactly this shape, lined up with the plate, which is as wide as the shape.
+    Footprint(Vec<SquarePos>),
+}
+
+impl PlateRequirement {
+    fn width(&self) -> u32 {
+        match self {
+            PlateRequirement::MinSquares { width, .. } => *width,
+            PlateRequirement::Footprint(shape) => get_dimens(shape).0 as u32,
+        }
+    }
+}
+
+/// A plate on the floor that powers every gate with the same link while it's weighed down.
+#[derive(Component)]
+pub struct PressurePlate {
+    position: Vec2,
+    width: u32,
+    requirement: PlateRequirement,
+    link: usize,
+    pressed: bool,
+}
+
+impl PressurePlate {
+    /// The centre of each square's width along the plate.
+    fn columns(&self) -> impl Iterator<Item = f32> + '_ {
+        (0..self.width).map(|i| self.column(i))
+    }
+
+    fn column(&self, i: u32) -> f32 {
+        self.position.x + (i as f32 - (self.width as f32 - 1.0) / 2.0) * PLAYER_WIDTH
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum GateKind {
+    /// Solid until powered, then opens.
+    Door,
+    /// Only solid while powered.
+    Bridge,
+}
+
+#[derive(Component)]
+pub struct Gate {
+    kind: GateKind,
+    link: usize,
+    unpowered_for: f32,
+    /// Whether the gate is actually solid, which can lag behind `wants_solid` while a player is in the way.
+    solid: bool,
+}
+
+impl Gate {
+    fn wants_solid(&self) -> bool {
+        let active = self.unpowered_for < GATE_CLOSE_DELAY;
+        match self.kind {
+            GateKind::Door => !active,
+            GateKind::Bridge => active,
+        }
+    }
+}
+
+/// Spawns a plate as wide as its requirement asks for. Like a checkpoint, the position is where a square
+/// standing in the middle of the plate would be.
+pub fn spawn_pressure_plate(
+    commands: &mut Commands,
+    x: f32,
+    y: f32,
+    mut requirement: PlateRequirement,
+    link: usize,
+    font: Handle<Font>,
+) -> Entity {
+    // kept sorted so that it can be compared against players' shapes
+    if let PlateRequirement::Footprint(shape) = &mut requirement {
+        normalize_squares(shape);
+        shape.sort_by_key(|square| (square.0, square.1));
+    }
+    let plate = PressurePlate {
+        position: Vec2::new(x, y),
+        width: requirement.width(),
+        requirement: requirement.clone(),
+        link,
+        pressed: false,
+    };
+    let bottom = (PLATE_HEIGHT - PLAYER_HEIGHT) / 2.0;
+    let mut builder = GeometryBuilder::new();
+    match &requirement {
+        PlateRequirement::MinSquares { width, .. } => {
+            builder = builder.add(&shapes::Rectangle {
+                extents: Vec2::new(PLAYER_WIDTH * *width as f32, PLATE_HEIGHT),
+                origin: RectangleOrigin::CustomCenter(Vec2::new(0.0, bottom)),
+            });
+        },
+        PlateRequirement::Footprint(shape) => {
+            // a segment under each of the shape's bottom squares
+            for square in shape.iter().filter(|square| square.1 == 0) {
+                builder = builder.add(&shapes::Rectangle {
+                    extents: Vec2::new(PLAYER_WIDTH - SEGMENT_GAP, PLATE_HEIGHT),
+                    origin: RectangleOrigin::CustomCenter(Vec2::new(plate.column(square.0 as u32) - x, bottom)),
+                });
+            }
+        },
+    }
+    let first_column = plate.column(0);
+    let mut entity = commands.spawn((
+        ShapeBundle {
+            path: builder.build(),
+            transform: Transform::from_xyz(x, y, -1.0),
+            ..default()
+        },
+        Fill::color(PLATE_COLOR),
+        plate,
+    ));
+    match requirement {
+        PlateRequirement::MinSquares { squares, .. } => {
+            entity.with_children(|parent| {
+                parent.spawn(Text2dBundle {
+                    text: Text::from_section(squares.to_string(), TextStyle {
+                        font,
+                        font_size: 14.0,
+                        color: PLATE_COLOR,
+                    }),
+                    // drawn on the floor underneath, in front of the block the plate sits on
+                    transform: Transform::from_xyz(0.0, bottom - PLATE_HEIGHT - 4.0, 2.0),
+                    ..default()
+                });
+            });
+        },
+        PlateRequirement::Footprint(shape) => {
+            // a faint outline of the shape that fits, standing on the plate
+            entity.with_children(|parent| {
+                parent.spawn((
+                    ShapeBundle {
+                        path: outline_path(&shape, OutlineStyle::CAVE),
+                        transform: Transform::from_xyz(first_column - x, 0.0, 0.1),
+                        ..default()
+                    },
+                    Stroke::new(PLATE_COLOR.with_a(FOOTPRINT_OUTLINE_ALPHA), FOOTPRINT_OUTLINE_WIDTH),
+                ));
+            });
+        },
+    }
+    entity.id()
+}
+
+/// Spawns a block that opens or closes when the plates with the same link are pressed.
+pub fn spawn_gate(commands: &mut Commands, kind: GateKind, link: usize, center: Vec2, size: Vec2) -> Entity {
+    let mut gate = Gate {
+        kind,
+        link,
+        unpowered_for: f32::INFINITY,
+        solid: false,
+    };
+    let solid = gate.wants_solid();
+    gate.solid = solid;
+    let mut entity = commands.spawn((
+        SpriteBundle {
+            sprite: Sprite {
+                color: GATE_COLOR.with_a(if solid { 1.0 } else { INACTIVE_GATE_ALPHA }),
+                custom_size: Some(size),
+                ..default()
+            },
+            transform: Transform::from_xyz(center.x, center.y, 0.0),
+            ..default()
+        },
+        Collider::cuboid(size.x / 2.0, size.y / 2.0),
+        gate,
+    ));
+    if !solid {
+        entity.insert(ColliderDisabled);
+    }
+    entity.id()
+}
+
+/// The player's shape, sorted so that shapes can be compared, and where its square (0, 0) would be.
+fn normalized_shape(player: &Player, transform: &Transform) -> (Vec<SquarePos>, Vec2) {
+    let mut squares = player.squares.clone();
+    let shift = normalize_squares(&mut squares);
+    squares.sort_by_key(|square| (square.0, square.1));
+    let origin = transform.translation.truncate() + shift.as_vec2() * Vec2::new(PLAYER_WIDTH, PLAYER_HEIGHT);
+    (squares, origin)
+}
+
+pub fn press_plates(
+    players: Query<(&Player, &Transform, &Grounded)>,
+    mut plates: Query<(&mut PressurePlate, &mut Fill)>,
+) {
+    let resting_players: Vec<(&Player, &Transform)> = players.iter()
+        .filter(|(player, _, grounded)| {
+            grounded.on_ground() && matches!(player.transformer_anim_state, TransformerAnimState::NotAnimating)
+        })
+        .map(|(player, transform, _)| (player, transform))
+        .collect();
+    // the bottom squares of every player that's standing still on something, and how big the player is
+    let resting: Vec<(Vec2, usize)> = resting_players.iter()
+        .flat_map(|(player, transform)| {
+            let position = transform.translation.truncate();
+            player.squares.iter()
+                .filter(|square| !player.squares.contains(&SquarePos(square.0, square.1 - 1)))
+                .map(move |square| {
+                    let offset = Vec2::new(square.0 as f32 * PLAYER_WIDTH, square.1 as f32 * PLAYER_HEIGHT);
+                    (position + offset, player.squares.len())
+                })
+        })
+        .collect();
+    let shapes: Vec<(Vec<SquarePos>, Vec2)> = resting_players.iter()
+        .map(|(player, transform)| normalized_shape(player, transform))
+        .collect();
+
+    for (mut plate, mut fill) in &mut plates {
+        // a column counts as covered by whichever square is over most of it
+        let on_column = |square: Vec2, column: f32| {
+            (square.x - column).abs() <= PLAYER_WIDTH / 2.0
+                && (square.y - plate.position.y).abs() <= PLAYER_HEIGHT * PLATE_TOLERANCE
+        };
+        let pressed = match &plate.requirement {
+            PlateRequirement::MinSquares { squares: count, .. } => resting.iter().any(|&(square, size)| {
+                size >= *count && plate.columns().any(|column| on_column(square, column))
+            }),
+            PlateRequirement::Footprint(footprint) => shapes.iter().any(|(shape, origin)| {
+                shape == footprint && on_column(*origin, plate.column(0))
+            }),
+        };
+        if plate.pressed != pressed {
+            plate.pressed = pressed;
+            *fill = Fill::color(if pressed { PLATE_PRESSED_COLOR } else { PLATE_COLOR });
+        }
+    }
+}
+
+type GateQuery = (Entity, &'static mut Gate, &'static mut Sprite, &'static Collider, &'static Transform);
+
+pub fn update_gates(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    plates: Query<&PressurePlate>,
+    mut gates: Query<GateQuery>,
+    players: Query<(), With<Player>>,
+    time: Res<Time>,
+) {
+    let is_player = |entity| players.contains(entity);
+    for (entity, mut gate, mut sprite, collider, transform) in &mut gates {
+        if plates.iter().any(|plate| plate.pressed && plate.link == gate.link) {
+            gate.unpowered_for = 0.0;
+        } else {
+            gate.unpowered_for += time.delta_seconds();
+        }
+        let solid = gate.wants_solid();
+        if solid == gate.solid {
+            continue;
+        }
+        // don't close on top of anyone, so wait until they're out of the way
+        let blocked = rapier_context.intersection_with_shape(
+            transform.translation.truncate(),
+            0.0,
+            collider,
+            QueryFilter::default().exclude_sensors().predicate(&is_player),
+        ).is_some();
+        if solid && blocked {
+            continue;
+        }
+        gate.solid = solid;
+        sprite.color.set_a(if solid { 1.0 } else { INACTIVE_GATE_ALPHA });
+        if solid {
+            commands.entity(entity).remove::<ColliderDisabled>();
+        } else {
+            commands.entity(entity).insert(ColliderDisabled);
+        }
+    }
+}
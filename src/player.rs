@@ -1,187 +1,485 @@
+use std::collections::VecDeque;
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 use bevy_prototype_lyon::prelude::*;
 use crate::{
     LevelTransitioning,
-    transformer::TransformerAnimState,
+    input::{Action, ActionState},
+    outline::{outline_path, OutlineStyle},
+    rewind::{Rewinding, RewindHistory},
+    surface::BlockKind,
+    transformer::{TransformerAnimState, MIN_SCALE},
 };
 
 pub const PLAYER_WIDTH: f32 = 50.0;
 pub const PLAYER_HEIGHT: f32 = 50.0;
 
-const PLAYER_COLOR: Color = Color::Rgba {
+pub const PLAYER_COLOR: Color = Color::Rgba {
     red: 0.96484375,
     green: 0.828125,
     blue: 0.1796875,
     alpha: 1.0,
 };
 
-#[derive(Copy, Clone)]
+pub const PLAYER_OUTLINE_COLOR: Color = Color::Rgba {
+    red: 0.7578125,
+    green: 0.546875,
+    blue: 0.0859375,
+    alpha: 1.0,
+};
+
+const PLAYER_TWO_COLOR: Color = Color::Rgba {
+    red: 0.3515625,
+    green: 0.71875,
+    blue: 0.88671875,
+    alpha: 1.0,
+};
+
+const PLAYER_TWO_OUTLINE_COLOR: Color = Color::Rgba {
+    red: 0.16796875,
+    green: 0.4765625,
+    blue: 0.65625,
+    alpha: 1.0,
+};
+
+pub const PLAYER_OUTLINE_WIDTH: f32 = 3.0;
+
+pub const PLAYER_GRAVITY_SCALE: f32 = 5.0;
+
+// how far below the player something can be while still counting as ground
+const GROUND_CHECK_DISTANCE: f32 = 2.0;
+const GROUND_PROBE_HALF_HEIGHT: f32 = 1.0;
+const GROUND_PROBE_INSET: f32 = 4.0;
+
+// how many transformations can be undone in a row
+const UNDO_HISTORY_LEN: usize = 32;
+
+#[derive(Copy, Clone, PartialEq)]
 pub struct SquarePos(pub i32, pub i32);
 
+pub fn get_dimens(squares: &[SquarePos]) -> (i32, i32) {
+    let mut max_x = 0;
+    let mut max_y = 0;
+    for square in squares {
+        if square.0 + 1 > max_x {
+            max_x = square.0 + 1;
+        }
+        if square.1 + 1 > max_y {
+            max_y = square.1 + 1;
+        }
+    }
+    (max_x, max_y)
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum MovementBackend {
+    /// A dynamic rigid body whose velocity is set every frame.
+    Dynamic,
+    /// A kinematic body moved by rapier's character controller, with slope handling and step-up.
+    Kinematic,
+}
+
+/// Tuning for how the player runs and jumps.
+#[derive(Resource)]
+pub struct MovementSettings {
+    pub backend: MovementBackend,
+    pub run_speed: f32,
+    pub jump_speed: f32,
+    /// How long after walking off a ledge the player can still jump, in seconds.
+    pub coyote_time: f32,
+    /// How long before landing a jump press is remembered, in seconds.
+    pub jump_buffer_time: f32,
+    /// Upward speed is multiplied by this when the jump key is let go early.
+    pub jump_cut: f32,
+    pub max_fall_speed: f32,
+    /// How quickly the player's speed catches up with the keys held while standing on ice, per second.
+    pub ice_traction: f32,
+}
+
+impl Default for MovementSettings {
+    fn default() -> Self {
+        MovementSettings {
+            backend: if cfg!(feature = "kinematic_controller") {
+                MovementBackend::Kinematic
+            } else {
+                MovementBackend::Dynamic
+            },
+            run_speed: 300.0,
+            jump_speed: 350.0,
+            coyote_time: 0.1,
+            jump_buffer_time: 0.12,
+            jump_cut: 0.5,
+            max_fall_speed: 900.0,
+            ice_traction: 3.0,
+        }
+    }
+}
+
+/// Shifts the squares so the lowest and leftmost ones are back at 0, returning how far they moved in grid units.
+/// The player's transform needs to move the other way to stay put on screen.
+pub fn normalize_squares(squares: &mut [SquarePos]) -> IVec2 {
+    let min_x = squares.iter().map(|square| square.0).min().unwrap_or(0);
+    let min_y = squares.iter().map(|square| square.1).min().unwrap_or(0);
+    for square in squares.iter_mut() {
+        *square = SquarePos(square.0 - min_x, square.1 - min_y);
+    }
+    IVec2::new(min_x, min_y)
+}
+
+/// What changed the player's shape, so that undoing it can tell whether a transformation was taken back.
+#[derive(Copy, Clone, PartialEq)]
+pub enum ShapeChange {
+    Transformation,
+    Pickup,
+}
+
+/// The shape of the player right before a transformation, and where they last stood before it.
+struct UndoState {
+    change: ShapeChange,
+    squares: Vec<SquarePos>,
+    carried: Vec<Entity>,
+    position: Vec3,
+}
+
+/// Centre of the polyomino's bounding box, relative to square (0, 0).
+pub fn get_center(squares: &[SquarePos]) -> Vec2 {
+    let (w, h) = get_dimens(squares);
+    Vec2::new((w - 1) as f32 * PLAYER_WIDTH / 2.0, (h - 1) as f32 * PLAYER_HEIGHT / 2.0)
+}
+
+pub fn get_collider(squares: &[SquarePos]) -> Collider {
+    let shape_tuples = squares.iter().map(|square|
+        (
+            Vec2::new(PLAYER_WIDTH * square.0 as f32, PLAYER_HEIGHT * square.1 as f32),
+            0.0,
+            Collider::cuboid(PLAYER_WIDTH/2.0, PLAYER_HEIGHT/2.0),
+        )
+    ).collect();
+
+    Collider::compound(shape_tuples)
+}
+
 #[derive(Component)]
 pub struct Player {
+    /// Which player this is, for picking their controls and colours. The first player is 0.
+    pub index: usize,
     is_jumping: bool,
+    time_since_grounded: f32,
+    time_since_jump_pressed: f32,
     pub transformer_anim_state: TransformerAnimState,
+    /// The point, relative to square (0, 0), that stays still while the player grows back to full size.
+    /// A rotated shape grows around its centre so that it turns in place.
+    pub grow_pivot: Vec2,
     pub squares: Vec<SquarePos>,
+    /// The other players fused into this one, who come back apart when it dies.
+    pub fused: Vec<usize>,
+    /// The loose squares stuck onto the shape. Any that aren't in here any more go back where they were found.
+    pub carried: Vec<Entity>,
+    undo_history: VecDeque<UndoState>,
+    // last place the player was standing outside of any transformer's reach
+    safe_position: Vec3,
 }
 
 impl Player {
-    fn new() -> Self {
+    fn new(index: usize, spawn_point: Vec3) -> Self {
         Player {
+            index,
             is_jumping: false,
+            time_since_grounded: 0.0,
+            time_since_jump_pressed: f32::INFINITY,
             transformer_anim_state: TransformerAnimState::NotAnimating,
+            grow_pivot: Vec2::ZERO,
             squares: vec![SquarePos(0, 0)],
+            fused: vec![],
+            carried: vec![],
+            undo_history: VecDeque::new(),
+            safe_position: spawn_point,
         }
     }
-    
-    pub fn get_dimens(&self) -> (i32, i32) {
-        let mut max_x = 0;
-        let mut max_y = 0;
-        for square in &self.squares {
-            if square.0 + 1 > max_x {
-                max_x = square.0 + 1;
-            }
-            if square.1 + 1 > max_y {
-                max_y = square.1 + 1;
+
+    /// Remembers the current position if the player is standing still enough to be put back there.
+    pub fn update_safe_position(&mut self, position: Vec3) {
+        if !self.is_jumping {
+            if let TransformerAnimState::NotAnimating = self.transformer_anim_state {
+                self.safe_position = position;
             }
         }
-        (max_x, max_y)
     }
 
-    pub fn get_shape(&self) -> Path {
-        let mut builder = GeometryBuilder::new();
-        for square in &self.squares {
-            builder = builder.add(&shapes::Rectangle {
-                extents: Vec2::new(PLAYER_WIDTH, PLAYER_HEIGHT),
-                origin: RectangleOrigin::CustomCenter(Vec2::new(PLAYER_WIDTH * square.0 as f32, PLAYER_HEIGHT * square.1 as f32)),
-            });
+    pub fn push_undo(&mut self, change: ShapeChange) {
+        if self.undo_history.len() == UNDO_HISTORY_LEN {
+            self.undo_history.pop_front();
         }
-        builder.build()
+        self.undo_history.push_back(UndoState {
+            change,
+            squares: self.squares.clone(),
+            carried: self.carried.clone(),
+            position: self.safe_position,
+        });
     }
 
-    pub fn get_collider(&self) -> Collider {
-        let shape_tuples = self.squares.iter().map(|square|
-            (
-                Vec2::new(PLAYER_WIDTH * square.0 as f32, PLAYER_HEIGHT * square.1 as f32),
-                0.0,
-                Collider::cuboid(PLAYER_WIDTH/2.0, PLAYER_HEIGHT/2.0),
-            )
-        ).collect();
+    pub fn clear_undo(&mut self) {
+        self.undo_history.clear();
+    }
 
-        Collider::compound(shape_tuples)
+    /// Drops the last undo step if rewinding has already brought the player back to it, and returns what
+    /// that step undid.
+    pub fn rewind_undo(&mut self, squares: &[SquarePos]) -> Option<ShapeChange> {
+        match self.undo_history.back() {
+            Some(undo_state) if undo_state.squares == squares => {
+                self.undo_history.pop_back().map(|undo_state| undo_state.change)
+            },
+            _ => None,
+        }
     }
 
-    pub fn add_square(&mut self, x: i32, y: i32) {
-        for square in &self.squares {
-            if square.0 == x && square.1 == y {
-                return;
-            }
+    /// Restores the shape from before the last change and returns the position to go back to, along with
+    /// what the change was.
+    pub fn pop_undo(&mut self) -> Option<(Vec3, ShapeChange)> {
+        let undo_state = self.undo_history.pop_back()?;
+        self.squares = undo_state.squares;
+        self.carried = undo_state.carried;
+        self.is_jumping = false;
+        self.transformer_anim_state = TransformerAnimState::NotAnimating;
+        Some((undo_state.position, undo_state.change))
+    }
+    
+    pub fn get_dimens(&self) -> (i32, i32) {
+        get_dimens(&self.squares)
+    }
+
+    pub fn get_shape(&self) -> Path {
+        outline_path(&self.squares, OutlineStyle::PLAYER)
+    }
+
+    pub fn get_collider(&self) -> Collider {
+        get_collider(&self.squares)
+    }
+
+    /// Fill and outline colours.
+    pub fn get_colors(&self) -> (Color, Color) {
+        match self.index {
+            0 => (PLAYER_COLOR, PLAYER_OUTLINE_COLOR),
+            _ => (PLAYER_TWO_COLOR, PLAYER_TWO_OUTLINE_COLOR),
         }
-        self.squares.push(SquarePos(x, y));
     }
 }
 
 
-pub fn spawn_player_at_point(commands: &mut Commands, spawn_point: (f32, f32)) {
-    let player = Player::new();
+pub fn spawn_player_at_point(commands: &mut Commands, spawn_point: (f32, f32), index: usize) {
+    let transform = Transform::from_xyz(spawn_point.0, spawn_point.1, 0.0);
+    spawn_player_entity(commands, Player::new(index, transform.translation), transform);
+}
+
+/// Spawns the player in the given shape, shrunk down and growing back to full size like coming out of a
+/// transformer.
+pub fn respawn_player_at_point(
+    commands: &mut Commands,
+    spawn_point: (f32, f32),
+    squares: Vec<SquarePos>,
+    carried: Vec<Entity>,
+    index: usize,
+) {
+    let transform = Transform::from_xyz(spawn_point.0, spawn_point.1, 0.0)
+        .with_scale(Vec3::new(MIN_SCALE, MIN_SCALE, 1.0));
+    let mut player = Player::new(index, transform.translation);
+    player.squares = squares;
+    player.carried = carried;
+    player.transformer_anim_state = TransformerAnimState::grow_in();
+    spawn_player_entity(commands, player, transform);
+}
+
+fn spawn_player_entity(commands: &mut Commands, player: Player, transform: Transform) {
+    let (color, outline_color) = player.get_colors();
     commands.spawn((
         ShapeBundle {
             path: player.get_shape(),
-            transform: Transform::from_xyz(spawn_point.0, spawn_point.1, 0.0),
+            transform,
             ..default()
         },
-        Fill::color(PLAYER_COLOR),
+        Fill::color(color),
+        Stroke::new(outline_color, PLAYER_OUTLINE_WIDTH),
         RigidBody::Dynamic,
         player.get_collider(),
-        ActiveEvents::CONTACT_FORCE_EVENTS,
         Sleeping::disabled(),
         Ccd::enabled(),
         LockedAxes::ROTATION_LOCKED,
         ColliderMassProperties::Density(2.0),
-        GravityScale(5.0),
+        GravityScale(PLAYER_GRAVITY_SCALE),
         Velocity::zero(),
+        Grounded::default(),
+        RewindHistory::default(),
         player,
     ));
 }
 
 
 pub fn spawn_player(mut commands: Commands) {
-    spawn_player_at_point(&mut commands, (-550.0, -200.0));  // the spawn point for the first level
+    spawn_player_at_point(&mut commands, (-550.0, -200.0), 0);  // the spawn point for the first level
+}
+
+/// Scales the player so that `pivot`, relative to square (0, 0), stays where it is.
+fn scale_around(transform: &mut Transform, pivot: Vec2, scale: f32) {
+    transform.translation -= (pivot * (scale - transform.scale.x)).extend(0.0);
+    transform.scale = Vec3::splat(scale);
 }
 
 pub fn move_player(
-    keyboard_input: Res<Input<KeyCode>>,
-    mut player_info: Query<(&mut Player, &mut Velocity, &mut Transform)>,
+    action_state: Res<ActionState>,
+    mut player_info: Query<(&mut Player, &mut Velocity, &mut Transform, &Grounded)>,
+    block_kinds: Query<&BlockKind>,
     level_transitioning: ResMut<LevelTransitioning>,
+    rewinding: Res<Rewinding>,
+    settings: Res<MovementSettings>,
+    time: Res<Time>,
 ) {
-    if level_transitioning.0 {
+    if level_transitioning.0 || rewinding.0 {
         return;
     }
-    for (mut player, mut velocity, mut transform) in &mut player_info {
+    for (mut player, mut velocity, mut transform, grounded) in &mut player_info {
+        player.transformer_anim_state.tick(time.delta_seconds());
         match player.transformer_anim_state {
             TransformerAnimState::NotAnimating => {
                 // move normally
-                let left = keyboard_input.pressed(KeyCode::Left);
-                let right = keyboard_input.pressed(KeyCode::Right);
+                let left = action_state.player_pressed(player.index, Action::MoveLeft);
+                let right = action_state.player_pressed(player.index, Action::MoveRight);
 
-                let x = 300.0 * (-(left as i8) + right as i8) as f32;
-                let y_delta = if keyboard_input.pressed(KeyCode::Up) && !player.is_jumping {
-                    player.is_jumping = true;
-                    350.0
+                if grounded.on_ground() {
+                    player.time_since_grounded = 0.0;
+                } else {
+                    player.time_since_grounded += time.delta_seconds();
+                }
+                if action_state.player_just_pressed(player.index, Action::Jump) {
+                    player.time_since_jump_pressed = 0.0;
                 } else {
-                    0.0
-                };
-                let y = velocity.linvel.y + y_delta;
+                    player.time_since_jump_pressed += time.delta_seconds();
+                }
+
+                let mut x = settings.run_speed * (-(left as i8) + right as i8) as f32;
+                let on_ice = grounded.0.is_some_and(|ground| block_kinds.get(ground) == Ok(&BlockKind::Ice));
+                if on_ice {
+                    x = velocity.linvel.x + (x - velocity.linvel.x) * (settings.ice_traction * time.delta_seconds()).min(1.0);
+                }
+                let mut y = velocity.linvel.y;
+                if player.time_since_jump_pressed <= settings.jump_buffer_time
+                        && player.time_since_grounded <= settings.coyote_time
+                        && !player.is_jumping {
+                    player.is_jumping = true;
+                    player.time_since_jump_pressed = f32::INFINITY;
+                    y = settings.jump_speed;
+                } else if player.is_jumping && action_state.player_just_released(player.index, Action::Jump) && y > 0.0 {
+                    y *= settings.jump_cut;
+                }
 
-                velocity.linvel = Vec2::new(x, y);
+                velocity.linvel = Vec2::new(x, y.max(-settings.max_fall_speed));
+                // landed before growing back to full size
+                if transform.scale != Vec3::ONE {
+                    scale_around(&mut transform, player.grow_pivot, 1.0);
+                }
             },
-            TransformerAnimState::MovingToward { orig_pos, transformer_pos } => {
+            TransformerAnimState::MovingToward { orig_pos, transformer_pos, tween, .. } => {
                 // move toward transformer
-                velocity.linvel = Vec2::new(
-                    transformer_pos.x - orig_pos.x,
-                    transformer_pos.y - orig_pos.y,
-                ) * 5.0;
-                transform.scale *= 0.9;
+                velocity.linvel = Vec2::ZERO;
+                let position = tween.lerp_vec2(orig_pos.truncate(), transformer_pos);
+                transform.translation = position.extend(orig_pos.z);
+                transform.scale = Vec3::splat(tween.lerp(1.0, MIN_SCALE));
             },
-            TransformerAnimState::MovingAway { orig_pos, transformer_pos, transformer_spit_direction } => {
-                // move away from transformer
-                velocity.linvel = Vec2::new(
-                    transformer_spit_direction.x * (transformer_pos.x - orig_pos.x).signum(),
-                    transformer_spit_direction.y,
-                );
-                if transform.scale.x < 1.0 {
-                    transform.scale *= 1.1;
-                }
-                if transform.scale.x > 1.0 {
-                    transform.scale = Vec3::new(1.0, 1.0, 1.0);
+            TransformerAnimState::MovingAway { velocity: eject_velocity, ballistic, grow, .. } => {
+                // move away from transformer, in a straight line unless it's meant to arc
+                if !ballistic {
+                    velocity.linvel = eject_velocity;
                 }
+                scale_around(&mut transform, player.grow_pivot, grow.lerp(MIN_SCALE, 1.0));
             },
-            TransformerAnimState::Falling => {
-                if transform.scale.x < 1.0 {
-                    transform.scale *= 1.1;
-                }
-                if transform.scale.x > 1.0 {
-                    transform.scale = Vec3::new(1.0, 1.0, 1.0);
-                }
+            TransformerAnimState::Falling { grow } => {
+                scale_around(&mut transform, player.grow_pivot, grow.lerp(MIN_SCALE, 1.0));
             },
         }
     }
 }
 
-pub fn set_jumping_false(
-    mut contact_events: EventReader<ContactForceEvent>,
-    mut players: Query<(Entity, &mut Player)>,
+/// What the player is standing on, if anything, found by casting a thin probe down from each of the
+/// player's bottom squares.
+#[derive(Component, Default)]
+pub struct Grounded(pub Option<Entity>);
+
+impl Grounded {
+    pub fn on_ground(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+fn find_ground(
+    rapier_context: &RapierContext,
+    block_kinds: &Query<&BlockKind>,
+    entity: Entity,
+    player: &Player,
+    transform: &Transform,
+    velocity: &Velocity,
+) -> Option<Entity> {
+    // narrower than a square so that walls beside the player don't count as ground
+    let probe = Collider::cuboid(PLAYER_WIDTH / 2.0 - GROUND_PROBE_INSET, GROUND_PROBE_HALF_HEIGHT);
+    let filter = QueryFilter::default().exclude_collider(entity).exclude_sensors();
+    let is_one_way = |block| block_kinds.get(block) == Ok(&BlockKind::OneWay);
+    let rising = velocity.linvel.y > 0.0;
+    let scale = transform.scale.truncate();
+    player.squares.iter()
+        .filter(|square| !player.squares.contains(&SquarePos(square.0, square.1 - 1)))
+        .find_map(|square| {
+            let bottom = Vec2::new(
+                PLAYER_WIDTH * square.0 as f32,
+                PLAYER_HEIGHT * square.1 as f32 - PLAYER_HEIGHT / 2.0 + GROUND_PROBE_HALF_HEIGHT,
+            );
+            let start = transform.translation.truncate() + bottom * scale;
+            // one-way blocks only hold the player up once they've come down onto them from above
+            let mut inside = vec![];
+            rapier_context.intersections_with_shape(start, 0.0, &probe, filter.predicate(&is_one_way), |block| {
+                inside.push(block);
+                true
+            });
+            let can_stand_on = |block| !(is_one_way(block) && (rising || inside.contains(&block)));
+            rapier_context.cast_shape(
+                start,
+                0.0,
+                Vec2::NEG_Y,
+                &probe,
+                GROUND_CHECK_DISTANCE,
+                filter.predicate(&can_stand_on),
+            ).map(|(ground, _)| ground)
+        })
+}
+
+type GroundingQuery = (
+    Entity,
+    &'static mut Player,
+    &'static mut Grounded,
+    &'static Transform,
+    &'static Velocity,
+    Option<&'static ColliderDisabled>,
+);
+
+pub fn update_grounded(
+    rapier_context: Res<RapierContext>,
+    mut players: Query<GroundingQuery>,
+    block_kinds: Query<&BlockKind>,
 ) {
-    for (entity, mut player) in &mut players {
-        for contact_event in contact_events.iter() {
-            if (contact_event.collider1 == entity || contact_event.collider2 == entity)
-                    && contact_event.total_force.y != 0.0 {
-                player.is_jumping = false;
-                if let TransformerAnimState::Falling {..} = player.transformer_anim_state {
-                    player.transformer_anim_state = TransformerAnimState::NotAnimating;
-                }
-            }
+    for (entity, mut player, mut grounded, transform, velocity, collider_disabled) in &mut players {
+        let ground = match collider_disabled {
+            Some(_) => None,
+            None => find_ground(&rapier_context, &block_kinds, entity, &player, transform, velocity),
+        };
+        if grounded.0 != ground {
+            grounded.0 = ground;
+        }
+        if ground.is_none() {
+            continue;
+        }
+        // moving up means the jump was only just made and hasn't landed yet
+        if velocity.linvel.y <= 0.0 {
+            player.is_jumping = false;
+        }
+        if let TransformerAnimState::Falling { .. } = player.transformer_anim_state {
+            player.transformer_anim_state = TransformerAnimState::NotAnimating;
         }
     }
 }
@@ -0,0 +1,63 @@
+use bevy::prelude::*;
+use crate::player::{Player, SquarePos, get_center, respawn_player_at_point};
+use crate::checkpoint::ActiveCheckpoint;
+use crate::map::Level;
+use crate::LevelTransitioning;
+
+#[derive(Event)]
+pub struct PlayerDied {
+    pub player: Entity,
+}
+
+#[derive(Resource, Default)]
+pub struct DeathCount(pub u32);
+
+pub fn check_out_of_bounds(
+    players: Query<(Entity, &Player, &Transform)>,
+    levels: Query<&Level>,
+    mut death_events: EventWriter<PlayerDied>,
+    level_transitioning: Res<LevelTransitioning>,
+) {
+    if level_transitioning.0 {
+        return;
+    }
+    let Some(level) = levels.iter().next() else { return };
+    for (entity, player, transform) in &players {
+        let center = transform.translation.truncate() + get_center(&player.squares) * transform.scale.truncate();
+        if !level.kill_bounds.contains(center) {
+            death_events.send(PlayerDied { player: entity });
+        }
+    }
+}
+
+pub fn respawn_dead_players(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut death_events: EventReader<PlayerDied>,
+    players: Query<&Player>,
+    levels: Query<&Level>,
+    active_checkpoint: Res<ActiveCheckpoint>,
+    mut death_count: ResMut<DeathCount>,
+) {
+    let mut dead_players: Vec<Entity> = death_events.iter().map(|event| event.player).collect();
+    dead_players.sort();
+    dead_players.dedup();
+    let Some(level) = levels.iter().next() else { return };
+
+    for entity in dead_players {
+        // already respawned, or cleaned up by a restart
+        let Ok(player) = players.get(entity) else { continue };
+        commands.entity(entity).despawn();
+        // a fused shape comes apart, and every player in it respawns on their own
+        for index in std::iter::once(player.index).chain(player.fused.iter().copied()) {
+            let (spawn_point, squares, carried) = active_checkpoint.respawn_for(level, index)
+                .unwrap_or((level.spawn_point_for(index), vec![SquarePos(0, 0)], vec![]));
+            respawn_player_at_point(&mut commands, spawn_point, squares, carried, index);
+        }
+        death_count.0 += 1;
+        commands.spawn(AudioBundle {
+            source: asset_server.load("sounds/woosh.wav"),
+            ..default()
+        });
+    }
+}
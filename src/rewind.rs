@@ -0,0 +1,110 @@
+use std::collections::VecDeque;
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use crate::player::{Player, SquarePos, ShapeChange};
+use crate::transformer::{TransformerAnimState, TransformationUndone, RotateAnimation};
+use crate::LevelTransitioning;
+use crate::input::{Action, ActionState};
+
+// ten seconds at the default fixed timestep
+const REWIND_HISTORY_LEN: usize = 600;
+
+#[derive(Resource)]
+pub struct Rewinding(pub bool);
+
+struct RewindSnapshot {
+    transform: Transform,
+    velocity: Velocity,
+    squares: Vec<SquarePos>,
+    carried: Vec<Entity>,
+    transformer_anim_state: TransformerAnimState,
+}
+
+#[derive(Component, Default)]
+pub struct RewindHistory {
+    snapshots: VecDeque<RewindSnapshot>,
+    /// The velocity of the last snapshot played back, to carry on with once the rewind key is let go.
+    resume_velocity: Option<Velocity>,
+}
+
+impl RewindHistory {
+    fn record(&mut self, transform: &Transform, velocity: &Velocity, player: &Player) {
+        if self.snapshots.len() == REWIND_HISTORY_LEN {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(RewindSnapshot {
+            transform: *transform,
+            velocity: *velocity,
+            squares: player.squares.clone(),
+            carried: player.carried.clone(),
+            transformer_anim_state: player.transformer_anim_state,
+        });
+    }
+
+    /// Forgets everything recorded so far, for changes that can't be played back.
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.resume_velocity = None;
+    }
+}
+
+/// Runs on the fixed timestep: records a snapshot of every player each tick,
+/// or plays the snapshots back in reverse while the rewind key is held.
+pub fn record_or_rewind(
+    mut commands: Commands,
+    action_state: Res<ActionState>,
+    mut player_info: Query<(
+        Entity,
+        &mut Player,
+        &mut RewindHistory,
+        &mut Transform,
+        &mut Velocity,
+        &mut Collider,
+        &mut Path,
+    )>,
+    mut rewinding: ResMut<Rewinding>,
+    mut undo_events: EventWriter<TransformationUndone>,
+    level_transitioning: Res<LevelTransitioning>,
+) {
+    if level_transitioning.0 {
+        rewinding.0 = false;
+        return;
+    }
+    rewinding.0 = action_state.pressed(Action::Rewind);
+
+    for (entity, mut player, mut history, mut transform, mut velocity, mut collider, mut path) in &mut player_info {
+        if !rewinding.0 {
+            if let Some(resume_velocity) = history.resume_velocity.take() {
+                *velocity = resume_velocity;
+            }
+            history.record(&transform, &velocity, &player);
+            continue;
+        }
+        // held still between snapshots, or physics would carry the player forward again until the next tick
+        *velocity = Velocity::zero();
+        let Some(snapshot) = history.snapshots.pop_back() else { continue };
+
+        *transform = snapshot.transform;
+        history.resume_velocity = Some(snapshot.velocity);
+        if snapshot.squares != player.squares {
+            if player.rewind_undo(&snapshot.squares) == Some(ShapeChange::Transformation) {
+                undo_events.send(TransformationUndone { player: entity });
+            }
+            player.squares = snapshot.squares;
+            player.carried = snapshot.carried;
+            *path = player.get_shape();
+            *collider = player.get_collider();
+            commands.entity(entity).remove::<RotateAnimation>();
+        }
+        match snapshot.transformer_anim_state {
+            TransformerAnimState::MovingToward { .. } | TransformerAnimState::MovingAway { .. } => {
+                commands.entity(entity).insert(ColliderDisabled);
+            },
+            TransformerAnimState::NotAnimating | TransformerAnimState::Falling { .. } => {
+                commands.entity(entity).remove::<ColliderDisabled>();
+            },
+        }
+        player.transformer_anim_state = snapshot.transformer_anim_state;
+    }
+}
@@ -0,0 +1,79 @@
+use bevy::{prelude::*, ecs::system::SystemParam};
+use bevy_rapier2d::prelude::*;
+use bevy_rapier2d::rapier::math::Vector;
+
+// how far from straight down the player can land on a one-way platform and still stand on it, in radians
+const ONE_WAY_ALLOWED_ANGLE: f32 = 0.1;
+/// The collision group one-way blocks are in, so the kinematic character controller can leave them out.
+pub const ONE_WAY_GROUP: Group = Group::GROUP_1;
+
+/// What a block is made of, which decides how the player moves against it.
+#[derive(Component, Copy, Clone, PartialEq)]
+pub enum BlockKind {
+    Solid,
+    /// Can be jumped up through from below and stood on from above.
+    OneWay,
+    /// Slippery, so the player speeds up and slows down gradually.
+    Ice,
+    /// Throws the player back up when they land on it.
+    Bouncy,
+}
+
+impl BlockKind {
+    pub fn get_color(&self) -> Color {
+        match self {
+            BlockKind::Solid => Color::rgb(1.0, 1.0, 1.0),
+            BlockKind::OneWay => Color::rgba(1.0, 1.0, 1.0, 0.5),
+            BlockKind::Ice => Color::rgb(0.7421875, 0.89453125, 0.97265625),
+            BlockKind::Bouncy => Color::rgb(0.47265625, 0.8203125, 0.4140625),
+        }
+    }
+
+    /// The rapier settings for the block's collider.
+    pub fn surface(&self) -> (Friction, Restitution, ActiveHooks, CollisionGroups) {
+        match self {
+            BlockKind::Solid => (
+                Friction::default(),
+                Restitution::default(),
+                ActiveHooks::empty(),
+                CollisionGroups::default(),
+            ),
+            BlockKind::OneWay => (
+                Friction::default(),
+                Restitution::default(),
+                ActiveHooks::MODIFY_SOLVER_CONTACTS,
+                CollisionGroups::new(ONE_WAY_GROUP, Group::ALL),
+            ),
+            BlockKind::Ice => (
+                Friction { coefficient: 0.0, combine_rule: CoefficientCombineRule::Min },
+                Restitution::default(),
+                ActiveHooks::empty(),
+                CollisionGroups::default(),
+            ),
+            BlockKind::Bouncy => (
+                Friction::default(),
+                Restitution { coefficient: 0.8, combine_rule: CoefficientCombineRule::Max },
+                ActiveHooks::empty(),
+                CollisionGroups::default(),
+            ),
+        }
+    }
+}
+
+/// Lets contacts with one-way blocks through unless they push up out of the top of the block.
+#[derive(SystemParam)]
+pub struct OneWayPlatformHooks<'w, 's> {
+    blocks: Query<'w, 's, &'static BlockKind>,
+}
+
+impl BevyPhysicsHooks for OneWayPlatformHooks<'_, '_> {
+    fn modify_solver_contacts(&self, context: ContactModificationContextView) {
+        let is_one_way = |entity| matches!(self.blocks.get(entity), Ok(BlockKind::OneWay));
+        // the allowed normal points out of the first collider, so it's flipped when the block comes second
+        if is_one_way(context.collider1()) {
+            context.raw.update_as_oneway_platform(&Vector::y(), ONE_WAY_ALLOWED_ANGLE);
+        } else if is_one_way(context.collider2()) {
+            context.raw.update_as_oneway_platform(&-Vector::y(), ONE_WAY_ALLOWED_ANGLE);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+use crate::input::{Action, ActionState};
+
+const TOUCH_BUTTON_COLOR: Color = Color::Rgba {
+    red: 0.60546875,
+    green: 0.6015625,
+    blue: 0.58984375,
+    alpha: 0.35,
+};
+
+const TOUCH_BUTTON_SIZE: f32 = 90.0;
+const TOUCH_BUTTON_MARGIN: f32 = 30.0;
+
+/// Whether the on-screen controls are shown. Switched on by the first touch.
+#[derive(Resource)]
+pub struct TouchControlsEnabled(pub bool);
+
+#[derive(Component)]
+pub struct TouchControls;
+
+/// A region of the screen that holds its action down while touched.
+#[derive(Component)]
+pub struct TouchButton(Action);
+
+fn touch_button(parent: &mut ChildBuilder, action: Action, label: &str, position: UiRect, text_style: &TextStyle) {
+    parent.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: position.left,
+                right: position.right,
+                top: position.top,
+                bottom: position.bottom,
+                width: Val::Px(TOUCH_BUTTON_SIZE),
+                height: Val::Px(TOUCH_BUTTON_SIZE),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            background_color: TOUCH_BUTTON_COLOR.into(),
+            ..default()
+        },
+        TouchButton(action),
+    )).with_children(|button| {
+        button.spawn(TextBundle::from_section(label, text_style.clone()));
+    });
+}
+
+pub fn spawn_touch_controls(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let text_style = TextStyle {
+        font: asset_server.load("fonts/bahnschrift.ttf"),
+        font_size: 32.0,
+        color: Color::WHITE,
+    };
+    let margin = Val::Px(TOUCH_BUTTON_MARGIN);
+    let next_to = Val::Px(TOUCH_BUTTON_MARGIN * 2.0 + TOUCH_BUTTON_SIZE);
+    commands.spawn((
+        NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                width: Val::Percent(100.0),
+                height: Val::Percent(100.0),
+                ..default()
+            },
+            visibility: Visibility::Hidden,
+            ..default()
+        },
+        TouchControls,
+    )).with_children(|parent| {
+        let bottom_left = |left| UiRect { left, bottom: margin, ..default() };
+        let bottom_right = |right| UiRect { right, bottom: margin, ..default() };
+        touch_button(parent, Action::MoveLeft, "<", bottom_left(margin), &text_style);
+        touch_button(parent, Action::MoveRight, ">", bottom_left(next_to), &text_style);
+        touch_button(parent, Action::Jump, "^", bottom_right(margin), &text_style);
+        touch_button(parent, Action::Restart, "R", UiRect { right: margin, top: margin, ..default() }, &text_style);
+    });
+}
+
+pub fn enable_touch_controls(
+    touches: Res<Touches>,
+    mut enabled: ResMut<TouchControlsEnabled>,
+    mut controls: Query<&mut Visibility, With<TouchControls>>,
+) {
+    if enabled.0 || touches.iter_just_pressed().next().is_none() {
+        return;
+    }
+    enabled.0 = true;
+    for mut visibility in &mut controls {
+        *visibility = Visibility::Inherited;
+    }
+}
+
+pub fn read_touch_actions(
+    touches: Res<Touches>,
+    enabled: Res<TouchControlsEnabled>,
+    buttons: Query<(&GlobalTransform, &TouchButton)>,
+    mut action_state: ResMut<ActionState>,
+) {
+    if !enabled.0 {
+        return;
+    }
+    // touch positions and ui nodes both measure from the top left of the window
+    for touch in touches.iter() {
+        for (transform, TouchButton(action)) in &buttons {
+            // every button is the same fixed size, so there's no need to wait for the layout to measure it
+            let rect = Rect::from_center_size(transform.translation().truncate(), Vec2::splat(TOUCH_BUTTON_SIZE));
+            if rect.contains(touch.position()) {
+                // the touch overlay always drives the first player
+                action_state.press(0, *action);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::input::touch::{touch_screen_input_system, TouchInput, TouchPhase};
+    use crate::input::clear_actions;
+    use super::*;
+
+    fn touch_app() -> App {
+        let mut app = App::new();
+        app.add_event::<TouchInput>()
+            .init_resource::<Touches>()
+            .init_resource::<ActionState>()
+            .insert_resource(TouchControlsEnabled(false))
+            .add_systems(PreUpdate, touch_screen_input_system)
+            .add_systems(Update, (clear_actions, enable_touch_controls, read_touch_actions).chain());
+        app
+    }
+
+    fn touch(app: &mut App, phase: TouchPhase, position: Vec2) {
+        app.world.send_event(TouchInput { phase, position, force: None, id: 0 });
+        app.update();
+    }
+
+    #[test]
+    fn first_touch_shows_the_controls() {
+        let mut app = touch_app();
+        let controls = app.world.spawn((TouchControls, Visibility::Hidden)).id();
+
+        app.update();
+        assert_eq!(*app.world.get::<Visibility>(controls).unwrap(), Visibility::Hidden);
+
+        touch(&mut app, TouchPhase::Started, Vec2::new(600.0, 400.0));
+        assert!(app.world.resource::<TouchControlsEnabled>().0);
+        assert_eq!(*app.world.get::<Visibility>(controls).unwrap(), Visibility::Inherited);
+    }
+
+    #[test]
+    fn touching_a_button_holds_its_action() {
+        let mut app = touch_app();
+        app.world.spawn((TouchButton(Action::Jump), GlobalTransform::from_xyz(1100.0, 700.0, 0.0)));
+
+        // a touch away from every button only brings up the controls
+        touch(&mut app, TouchPhase::Started, Vec2::new(600.0, 400.0));
+        assert!(!app.world.resource::<ActionState>().pressed(Action::Jump));
+        touch(&mut app, TouchPhase::Ended, Vec2::new(600.0, 400.0));
+
+        touch(&mut app, TouchPhase::Started, Vec2::new(1110.0, 690.0));
+        assert!(app.world.resource::<ActionState>().player_just_pressed(0, Action::Jump));
+
+        // sliding off the button lets go of it
+        touch(&mut app, TouchPhase::Moved, Vec2::new(900.0, 690.0));
+        assert!(app.world.resource::<ActionState>().player_just_released(0, Action::Jump));
+    }
+}
@@ -0,0 +1,138 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use bevy_prototype_lyon::prelude::*;
+use crate::player::{Player, SquarePos, PLAYER_GRAVITY_SCALE, get_collider};
+use crate::transformer::{Transformer, Ejection, EJECT_TIMEOUT};
+use crate::{WINDOW_WIDTH, WINDOW_HEIGHT};
+
+const TRAJECTORY_COLOR: Color = Color::Rgba {
+    red: 0.3515625,
+    green: 0.78515625,
+    blue: 0.9296875,
+    alpha: 0.8,
+};
+
+const TRAJECTORY_WIDTH: f32 = 2.0;
+const STEP: f32 = 1.0 / 60.0;
+// give up on trajectories that take longer than this to land
+const MAX_STEPS: usize = 240;
+
+#[derive(Resource)]
+pub struct TrajectoryOverlayEnabled(pub bool);
+
+#[derive(Component)]
+pub struct TrajectoryOverlay;
+
+fn is_off_screen(point: Vec2) -> bool {
+    point.x.abs() > WINDOW_WIDTH / 2.0 || point.y.abs() > WINDOW_HEIGHT / 2.0
+}
+
+/// Steps the same motion the player goes through after a transformer spits them out: flying out until they
+/// leave its reach, then falling until they hit something.
+fn predict_trajectory(
+    rapier_context: &RapierContext,
+    transformer: &Transformer,
+    collider: &Collider,
+    velocity: Vec2,
+    gravity: f32,
+    filter: QueryFilter,
+) -> Vec<Vec2> {
+    let mut position = transformer.position;
+    let mut velocity = velocity;
+    let mut elapsed = 0.0;
+    let mut leaving = true;
+    let mut points = vec![position];
+
+    for _ in 0..MAX_STEPS {
+        if leaving {
+            if transformer.ejection.is_ballistic() {
+                velocity.y += gravity * STEP;
+            }
+            position += velocity * STEP;
+            elapsed += STEP;
+            leaving = transformer.distance_to(collider, position) < transformer.radius && elapsed < EJECT_TIMEOUT;
+        } else {
+            velocity.y += gravity * STEP;
+            if let Some((_, toi)) = rapier_context.cast_shape(position, 0.0, velocity, collider, STEP, filter) {
+                points.push(position + velocity * toi.toi);
+                break;
+            }
+            position += velocity * STEP;
+        }
+        points.push(position);
+        if is_off_screen(position) {
+            break;
+        }
+    }
+    points
+}
+
+pub fn toggle_trajectory_overlay(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut overlay_enabled: ResMut<TrajectoryOverlayEnabled>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F3) {
+        overlay_enabled.0 = !overlay_enabled.0;
+    }
+}
+
+pub fn update_trajectory_overlay(
+    mut commands: Commands,
+    overlay_enabled: Res<TrajectoryOverlayEnabled>,
+    rapier_context: Res<RapierContext>,
+    rapier_config: Res<RapierConfiguration>,
+    players: Query<(Entity, &Player)>,
+    transformers: Query<&Transformer>,
+    mut overlays: Query<(Entity, &mut Path), With<TrajectoryOverlay>>,
+) {
+    if !overlay_enabled.0 {
+        for (overlay_entity, _) in &overlays {
+            commands.entity(overlay_entity).despawn();
+        }
+        return;
+    }
+
+    let gravity = rapier_config.gravity.y * PLAYER_GRAVITY_SCALE;
+    let player_entities: Vec<Entity> = players.iter().map(|(entity, _)| entity).collect();
+    let is_not_player = |entity| !player_entities.contains(&entity);
+    let filter = QueryFilter::default().exclude_sensors().predicate(&is_not_player);
+    let mut builder = PathBuilder::new();
+    for transformer in &transformers {
+        // the shape that would come out if the player went in right now
+        let squares = match players.iter().find(|(_, player)| player.index == 0) {
+            Some((_, player)) => transformer.transformation.transformed(player),
+            None => vec![SquarePos(0, 0)],
+        };
+        let collider = get_collider(&squares);
+
+        // mirrored ejections go a different way depending on which side the player comes in from
+        let approaches = match transformer.ejection {
+            Ejection::Mirrored(_) => vec![Vec2::NEG_X, Vec2::X],
+            Ejection::Fixed(_) | Ejection::ArcTo(_) => vec![Vec2::ZERO],
+        };
+        for approach in approaches {
+            let velocity = transformer.ejection.velocity(transformer.position, transformer.position + approach, gravity);
+            let points = predict_trajectory(&rapier_context, transformer, &collider, velocity, gravity, filter);
+            builder.move_to(points[0]);
+            for point in &points[1..] {
+                builder.line_to(*point);
+            }
+        }
+    }
+    let path = builder.build();
+
+    match overlays.get_single_mut() {
+        Ok((_, mut overlay_path)) => *overlay_path = path,
+        Err(_) => {
+            commands.spawn((
+                ShapeBundle {
+                    path,
+                    transform: Transform::from_xyz(0.0, 0.0, 2.0),
+                    ..default()
+                },
+                Stroke::new(TRAJECTORY_COLOR, TRAJECTORY_WIDTH),
+                TrajectoryOverlay,
+            ));
+        },
+    }
+}
@@ -1,13 +1,123 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::*;
 use bevy_prototype_lyon::prelude::*;
-use crate::player::{Player, SquarePos};
+use crate::player::{
+    Player, SquarePos, ShapeChange, PLAYER_WIDTH, PLAYER_HEIGHT, PLAYER_OUTLINE_WIDTH,
+    PLAYER_GRAVITY_SCALE, get_dimens, get_center,
+};
+use crate::outline::{outline_path, OutlineStyle};
+use crate::rewind::Rewinding;
+use crate::tween::{Easing, Tween};
 
+// how close the player has to be before a transformer shows what it would do to them
+const PREVIEW_RADIUS: f32 = 200.0;
+const PREVIEW_SCALE: f32 = 0.5;
+const PREVIEW_ALPHA: f32 = 0.4;
+// gap between the transformer's centre and the bottom of the preview
+const PREVIEW_OFFSET: f32 = 80.0;
+
+// how long it takes to get sucked into a transformer, and to grow back to full size afterwards
+const SUCK_IN_DURATION: f32 = 0.25;
+const GROW_DURATION: f32 = 0.3;
+// how small the player gets while inside a transformer
+pub const MIN_SCALE: f32 = 0.2;
+// the player is let go after this long even if they haven't left the transformer's reach yet
+pub const EJECT_TIMEOUT: f32 = 0.6;
+// how long a transformer waits after spitting the player out before it can grab anyone again
+const COOLDOWN: f32 = 1.0;
+// how far above the higher of its two ends an arc ejection peaks
+const ARC_APEX_HEIGHT: f32 = 100.0;
+const ROTATE_DURATION: f32 = 0.25;
+
+#[derive(Copy, Clone)]
 pub enum TransformerAnimState {
     NotAnimating,
-    MovingToward { orig_pos: Vec3, transformer_pos: Vec2 },
-    MovingAway { orig_pos: Vec3, transformer_pos: Vec2, transformer_spit_direction: Vec2 },
-    Falling,
+    MovingToward { transformer: Entity, orig_pos: Vec3, transformer_pos: Vec2, tween: Tween },
+    MovingAway {
+        transformer: Entity,
+        velocity: Vec2,
+        /// Whether gravity acts on the player on the way out, instead of them flying in a straight line.
+        ballistic: bool,
+        grow: Tween,
+        elapsed: f32,
+    },
+    /// Left the transformer but hasn't landed yet; keeps growing if it hasn't finished.
+    Falling { grow: Tween },
+}
+
+impl TransformerAnimState {
+    pub fn suck_in(transformer: Entity, orig_pos: Vec3, transformer_pos: Vec2) -> Self {
+        TransformerAnimState::MovingToward {
+            transformer,
+            orig_pos,
+            transformer_pos,
+            tween: Tween::new(SUCK_IN_DURATION, Easing::QuadInOut),
+        }
+    }
+
+    pub fn spit_out(transformer: Entity, velocity: Vec2, ballistic: bool) -> Self {
+        TransformerAnimState::MovingAway {
+            transformer,
+            velocity,
+            ballistic,
+            grow: Tween::new(GROW_DURATION, Easing::CubicOut),
+            elapsed: 0.0,
+        }
+    }
+
+    pub fn grow_in() -> Self {
+        TransformerAnimState::Falling {
+            grow: Tween::new(GROW_DURATION, Easing::CubicOut),
+        }
+    }
+
+    /// Advances whichever animation is playing by `delta` seconds.
+    pub fn tick(&mut self, delta: f32) {
+        match self {
+            TransformerAnimState::MovingToward { tween, .. } => tween.tick(delta),
+            TransformerAnimState::MovingAway { grow, elapsed, .. } => {
+                grow.tick(delta);
+                *elapsed += delta;
+            },
+            TransformerAnimState::Falling { grow } => grow.tick(delta),
+            TransformerAnimState::NotAnimating => {},
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum Ejection {
+    /// Always thrown out with this velocity.
+    Fixed(Vec2),
+    /// Thrown out with this velocity, with x flipped so the player carries on the way they came in.
+    Mirrored(Vec2),
+    /// Thrown in an arc that lands on this point.
+    ArcTo(Vec2),
+}
+
+impl Ejection {
+    /// The velocity to throw the player out at, given where they were when they got sucked in and how
+    /// strong gravity is.
+    pub fn velocity(&self, transformer_pos: Vec2, orig_pos: Vec2, gravity: f32) -> Vec2 {
+        match *self {
+            Ejection::Fixed(velocity) => velocity,
+            Ejection::Mirrored(velocity) => Vec2::new(
+                velocity.x * (transformer_pos.x - orig_pos.x).signum(),
+                velocity.y,
+            ),
+            Ejection::ArcTo(target) => {
+                let gravity = gravity.abs();
+                let apex = transformer_pos.y.max(target.y) + ARC_APEX_HEIGHT;
+                let velocity_y = (2.0 * gravity * (apex - transformer_pos.y)).sqrt();
+                let flight_time = velocity_y / gravity + (2.0 * (apex - target.y) / gravity).sqrt();
+                Vec2::new((target.x - transformer_pos.x) / flight_time, velocity_y)
+            },
+        }
+    }
+
+    pub fn is_ballistic(&self) -> bool {
+        matches!(self, Ejection::ArcTo(_))
+    }
 }
 
 #[derive(Copy, Clone)]
@@ -18,21 +128,48 @@ pub enum Transformation {
 }
 
 impl Transformation {
-    fn apply(&self, player: &mut Player) {
+    /// Returns the squares the player would have after this transformation, without changing the player.
+    pub fn transformed(&self, player: &Player) -> Vec<SquarePos> {
         let (w, h) = player.get_dimens();
+        let mut squares = player.squares.clone();
+        let mut add_square = |square: SquarePos| {
+            if !squares.contains(&square) {
+                squares.push(square);
+            }
+        };
         match self {
             Transformation::AddRight => {
-                player.add_square(w, 0);
+                add_square(SquarePos(w, 0));
             },
             Transformation::AddTop => {
-                player.add_square(0, h);
+                add_square(SquarePos(0, h));
             },
             Transformation::RotateCw => {
-                for square in &mut player.squares {
+                for square in &mut squares {
                     *square = SquarePos(square.1, w - square.0 - 1);
                 }
             }
         }
+        squares
+    }
+
+    /// Applies the transformation and returns how far the player has to move so that it looks like the
+    /// shape turned on the spot, rather than around square (0, 0).
+    fn apply(&self, player: &mut Player) -> Vec2 {
+        let before = get_center(&player.squares);
+        player.squares = self.transformed(player);
+        match self {
+            Transformation::RotateCw => before - get_center(&player.squares),
+            Transformation::AddRight | Transformation::AddTop => Vec2::ZERO,
+        }
+    }
+
+    pub fn get_name(&self) -> &str {
+        match self {
+            Transformation::AddRight => "add right",
+            Transformation::AddTop => "add top",
+            Transformation::RotateCw => "rotate",
+        }
     }
 
     fn get_sprite_path(&self) -> &str {
@@ -44,23 +181,40 @@ impl Transformation {
     }
 }
 
+#[derive(Event)]
+pub struct TransformationApplied {
+    pub player: Entity,
+    pub transformation: Transformation,
+}
+
+#[derive(Event)]
+pub struct TransformationUndone {
+    pub player: Entity,
+}
+
 #[derive(Component)]
 pub struct Transformer {
-    position: Vec2,
-    radius: f32,
-    transformation: Transformation,
-    spit_direction: Vec2,
+    pub position: Vec2,
+    pub radius: f32,
+    pub transformation: Transformation,
+    pub ejection: Ejection,
+    cooldown: f32,
 }
 
 impl Transformer {
-    fn new(x: f32, y: f32, transformation: Transformation, spit_direction: Vec2) -> Self {
+    fn new(x: f32, y: f32, transformation: Transformation, ejection: Ejection) -> Self {
         Transformer {
             position: Vec2::new(x, y),
             radius: 35.0,
             transformation,
-            spit_direction,
+            ejection,
+            cooldown: 0.0,
         }
     }
+
+    pub fn distance_to(&self, collider: &Collider, position: Vec2) -> f32 {
+        collider.distance_to_point(position, 0.0, self.position, true)
+    }
 }
 
 
@@ -71,8 +225,8 @@ pub struct TransformerBundle {
 }
 
 impl TransformerBundle {
-    pub fn new(x: f32, y: f32, transformation: Transformation, spit_direction: Vec2, asset_server: &Res<AssetServer>) -> Self {
-        let transformer = Transformer::new(x, y, transformation, spit_direction);
+    pub fn new(x: f32, y: f32, transformation: Transformation, ejection: Ejection, asset_server: &Res<AssetServer>) -> Self {
+        let transformer = Transformer::new(x, y, transformation, ejection);
         TransformerBundle {
             sprite_bundle: SpriteBundle {
                 texture: asset_server.load(transformation.get_sprite_path()),
@@ -85,56 +239,250 @@ impl TransformerBundle {
 }
 
 
+pub fn tick_transformer_cooldowns(
+    mut transformers: Query<&mut Transformer>,
+    rewinding: Res<Rewinding>,
+    time: Res<Time>,
+) {
+    if rewinding.0 {
+        return;
+    }
+    for mut transformer in &mut transformers {
+        if transformer.cooldown > 0.0 {
+            transformer.cooldown = (transformer.cooldown - time.delta_seconds()).max(0.0);
+        }
+    }
+}
+
+/// Keeps players that are being pulled in heading for where their transformer is now, since it might be moving.
+pub fn follow_capturing_transformers(mut players: Query<&mut Player>, transformers: Query<&Transformer>) {
+    for mut player in &mut players {
+        if let TransformerAnimState::MovingToward { transformer, ref mut transformer_pos, .. } = player.transformer_anim_state {
+            if let Ok(transformer) = transformers.get(transformer) {
+                *transformer_pos = transformer.position;
+            }
+        }
+    }
+}
+
+/// Runs the capture sequence: grab the nearest ready transformer in reach, pull the player into its centre,
+/// transform them, then spit them out until they leave its reach or time out.
 pub fn apply_transformations(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
-    mut player_info: Query<(Entity, &mut Player, &mut Collider, &mut Path, &mut Transform)>,
-    transformers: Query<&Transformer>,
+    mut player_info: Query<(Entity, &mut Player, &mut Collider, &mut Path, &mut Transform, &mut Velocity)>,
+    mut transformers: Query<(Entity, &mut Transformer)>,
+    mut transformation_events: EventWriter<TransformationApplied>,
+    rewinding: Res<Rewinding>,
+    rapier_config: Res<RapierConfiguration>,
 ) {
-    for (player_entity, mut player, mut collider, mut path, player_transform) in &mut player_info {
-        let mut collided_with_transformer = false;
-        for transformer in &transformers {
+    if rewinding.0 {
+        return;
+    }
+
+    for (player_entity, mut player, mut collider, mut path, mut player_transform, mut velocity) in &mut player_info {
+        match player.transformer_anim_state {
+            TransformerAnimState::NotAnimating => {
+                let mut in_reach = false;
+                let mut nearest: Option<(Entity, f32)> = None;
+                for (transformer_entity, transformer) in &transformers {
+                    let distance = transformer.distance_to(&collider, player_transform.translation.truncate());
+                    if distance >= transformer.radius {
+                        continue;
+                    }
+                    in_reach = true;
+                    // when reaches overlap, the closest transformer wins instead of whichever came first
+                    if transformer.cooldown <= 0.0 && nearest.is_none_or(|(_, nearest_distance)| distance < nearest_distance) {
+                        nearest = Some((transformer_entity, distance));
+                    }
+                }
+
+                if let Some((transformer_entity, _)) = nearest {
+                    let (_, transformer) = transformers.get(transformer_entity).unwrap();
+                    player.transformer_anim_state = TransformerAnimState::suck_in(
+                        transformer_entity,
+                        player_transform.translation,
+                        transformer.position,
+                    );
+                    commands.entity(player_entity).insert(ColliderDisabled);
+                    commands.spawn(AudioBundle {
+                        source: asset_server.load("sounds/woosh_fast2.wav"),
+                        ..default()
+                    });
+                } else if !in_reach {
+                    player.update_safe_position(player_transform.translation);
+                }
+            },
+            TransformerAnimState::MovingToward { transformer: transformer_entity, orig_pos, tween, .. } if tween.finished() => {
+                let Ok((_, transformer)) = transformers.get(transformer_entity) else {
+                    // the transformer went away mid-capture, so just let go
+                    commands.entity(player_entity).remove::<ColliderDisabled>();
+                    player.transformer_anim_state = TransformerAnimState::grow_in();
+                    continue;
+                };
+                velocity.linvel = transformer.ejection.velocity(
+                    transformer.position,
+                    orig_pos.truncate(),
+                    rapier_config.gravity.y * PLAYER_GRAVITY_SCALE,
+                );
+                player.transformer_anim_state = TransformerAnimState::spit_out(
+                    transformer_entity,
+                    velocity.linvel,
+                    transformer.ejection.is_ballistic(),
+                );
+                player.push_undo(ShapeChange::Transformation);
+                // the centre stays put at the current size, and growing back keeps it there after a rotation
+                let shift = transformer.transformation.apply(&mut player) * player_transform.scale.truncate();
+                player_transform.translation += shift.extend(0.0);
+                player.grow_pivot = match transformer.transformation {
+                    Transformation::RotateCw => get_center(&player.squares),
+                    Transformation::AddRight | Transformation::AddTop => Vec2::ZERO,
+                };
+                if let Transformation::RotateCw = transformer.transformation {
+                    commands.entity(player_entity).insert(RotateAnimation {
+                        tween: Tween::new(ROTATE_DURATION, Easing::CubicOut),
+                    });
+                }
+                transformation_events.send(TransformationApplied {
+                    player: player_entity,
+                    transformation: transformer.transformation,
+                });
+                *path = player.get_shape();
+                *collider = player.get_collider();
+            },
+            TransformerAnimState::MovingAway { transformer: transformer_entity, grow, elapsed, .. } => {
+                let left_reach = match transformers.get(transformer_entity) {
+                    Ok((_, transformer)) => {
+                        transformer.distance_to(&collider, player_transform.translation.truncate()) >= transformer.radius
+                    },
+                    Err(_) => true,
+                };
+                if left_reach || elapsed >= EJECT_TIMEOUT {
+                    commands.entity(player_entity).remove::<ColliderDisabled>();
+                    player.transformer_anim_state = TransformerAnimState::Falling { grow };
+                    if let Ok((_, mut transformer)) = transformers.get_mut(transformer_entity) {
+                        transformer.cooldown = COOLDOWN;
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+}
+
+
+#[derive(Component)]
+pub struct TransformerPreview {
+    transformer: Entity,
+    squares: Vec<SquarePos>,
+}
+
+fn preview_transform(transformer_pos: Vec2, squares: &[SquarePos]) -> Transform {
+    let (w, _) = get_dimens(squares);
+    Transform::from_xyz(
+        transformer_pos.x - (w - 1) as f32 * PLAYER_WIDTH * PREVIEW_SCALE / 2.0,
+        transformer_pos.y + PREVIEW_OFFSET + PLAYER_HEIGHT * PREVIEW_SCALE / 2.0,
+        1.0,
+    ).with_scale(Vec3::new(PREVIEW_SCALE, PREVIEW_SCALE, 1.0))
+}
+
+type PreviewQuery = (
+    Entity,
+    &'static mut TransformerPreview,
+    &'static mut Path,
+    &'static mut Transform,
+    &'static mut Fill,
+    &'static mut Stroke,
+);
+
+pub fn preview_transformations(
+    mut commands: Commands,
+    players: Query<(&Player, &Collider, &Transform)>,
+    transformers: Query<(Entity, &Transformer)>,
+    mut previews: Query<PreviewQuery, Without<Player>>,
+) {
+    for (transformer_entity, transformer) in &transformers {
+        let nearby_player = players.iter().find(|(player, collider, player_transform)| {
             let distance = collider.distance_to_point(
-                Vec2::new(player_transform.translation.x, player_transform.translation.y),
+                player_transform.translation.truncate(),
                 0.0,
                 transformer.position,
                 true
             );
-            if distance < transformer.radius {
-                collided_with_transformer = true;
-                match player.transformer_anim_state {
-                    TransformerAnimState::NotAnimating => {
-                        player.transformer_anim_state = TransformerAnimState::MovingToward {
-                            orig_pos: player_transform.translation,
-                            transformer_pos: transformer.position,
-                        };
-                        commands.entity(player_entity).insert(ColliderDisabled);
-                        commands.spawn(AudioBundle {
-                            source: asset_server.load("sounds/woosh_fast2.wav"),
-                            ..default()
-                        });
-                    },
-                    TransformerAnimState::MovingToward { orig_pos, transformer_pos } => {
-                        if transformer_pos == transformer.position && distance < 0.01 {
-                            player.transformer_anim_state = TransformerAnimState::MovingAway {
-                                orig_pos,
-                                transformer_pos: transformer.position,
-                                transformer_spit_direction: transformer.spit_direction,
-                            };
-                            transformer.transformation.apply(&mut player);
-                            *path = player.get_shape();
-                            *collider = player.get_collider();
-                        }
-                    },
-                    _ => {},
+            matches!(player.transformer_anim_state, TransformerAnimState::NotAnimating) && distance < PREVIEW_RADIUS
+        });
+        let preview = previews.iter_mut().find(|(_, preview, ..)| preview.transformer == transformer_entity);
+
+        match (nearby_player, preview) {
+            (Some((player, _, _)), Some((_, mut preview, mut path, mut transform, mut fill, mut stroke))) => {
+                let squares = transformer.transformation.transformed(player);
+                // in co-op the other player may have walked up instead
+                let (color, outline_color) = player.get_colors();
+                fill.color = color.with_a(PREVIEW_ALPHA);
+                stroke.color = outline_color.with_a(PREVIEW_ALPHA);
+                // moving transformers carry their preview along with them
+                *transform = preview_transform(transformer.position, &squares);
+                if squares != preview.squares {
+                    *path = outline_path(&squares, OutlineStyle::PLAYER);
+                    preview.squares = squares;
                 }
-            }
+            },
+            (Some((player, _, _)), None) => {
+                let squares = transformer.transformation.transformed(player);
+                let (color, outline_color) = player.get_colors();
+                commands.spawn((
+                    ShapeBundle {
+                        path: outline_path(&squares, OutlineStyle::PLAYER),
+                        transform: preview_transform(transformer.position, &squares),
+                        ..default()
+                    },
+                    Fill::color(color.with_a(PREVIEW_ALPHA)),
+                    Stroke::new(outline_color.with_a(PREVIEW_ALPHA), PLAYER_OUTLINE_WIDTH),
+                    TransformerPreview { transformer: transformer_entity, squares },
+                ));
+            },
+            (None, Some((preview_entity, ..))) => {
+                commands.entity(preview_entity).despawn();
+            },
+            (None, None) => {},
         }
-        if !collided_with_transformer {
-            if let TransformerAnimState::MovingAway { .. } = player.transformer_anim_state {
-                commands.entity(player_entity).remove::<ColliderDisabled>();
-                player.transformer_anim_state = TransformerAnimState::Falling;
-            }
+    }
+
+    // the level was switched out from under the preview
+    for (preview_entity, preview, ..) in &previews {
+        if !transformers.contains(preview.transformer) {
+            commands.entity(preview_entity).despawn();
+        }
+    }
+}
+
+
+/// Turns the drawn shape from its old orientation to its new one after a rotation. Only the path is turned;
+/// the collider takes the new shape straight away.
+#[derive(Component)]
+pub struct RotateAnimation {
+    tween: Tween,
+}
+
+pub fn animate_rotations(
+    mut commands: Commands,
+    mut players: Query<(Entity, &Player, &mut Path, &mut RotateAnimation)>,
+    time: Res<Time>,
+) {
+    for (entity, player, mut path, mut rotate_animation) in &mut players {
+        rotate_animation.tween.tick(time.delta_seconds());
+        if rotate_animation.tween.finished() {
+            *path = player.get_shape();
+            commands.entity(entity).remove::<RotateAnimation>();
+            continue;
         }
+
+        // the new shape turned back a quarter turn anticlockwise is the old one
+        let angle = rotate_animation.tween.lerp(std::f32::consts::FRAC_PI_2, 0.0);
+        let center = get_center(&player.squares);
+        let rotation = tess::geom::Transform::translation(-center.x, -center.y)
+            .then_rotate(tess::geom::Angle::radians(angle))
+            .then_translate(tess::geom::vector(center.x, center.y));
+        *path = Path(player.get_shape().0.transformed(&rotation));
     }
 }
@@ -0,0 +1,59 @@
+use bevy::prelude::*;
+
+#[derive(Copy, Clone)]
+pub enum Easing {
+    QuadInOut,
+    CubicOut,
+}
+
+impl Easing {
+    /// Maps linear progress in `[0, 1]` onto the curve.
+    pub fn ease(&self, t: f32) -> f32 {
+        match self {
+            Easing::QuadInOut => if t < 0.5 {
+                2.0 * t * t
+            } else {
+                1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+            },
+            Easing::CubicOut => 1.0 - (1.0 - t).powi(3),
+        }
+    }
+}
+
+/// Progress through an animation that takes a fixed amount of time, regardless of frame rate.
+#[derive(Copy, Clone)]
+pub struct Tween {
+    elapsed: f32,
+    duration: f32,
+    easing: Easing,
+}
+
+impl Tween {
+    pub fn new(duration: f32, easing: Easing) -> Self {
+        Tween { elapsed: 0.0, duration, easing }
+    }
+
+    pub fn tick(&mut self, delta: f32) {
+        self.elapsed = (self.elapsed + delta).min(self.duration);
+    }
+
+    pub fn finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+
+    /// Eased progress from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        if self.duration <= 0.0 {
+            return 1.0;
+        }
+        self.easing.ease(self.elapsed / self.duration)
+    }
+
+    pub fn lerp(&self, from: f32, to: f32) -> f32 {
+        from + (to - from) * self.progress()
+    }
+
+    pub fn lerp_vec2(&self, from: Vec2, to: Vec2) -> Vec2 {
+        from.lerp(to, self.progress())
+    }
+}